@@ -0,0 +1,427 @@
+use crate::Specialisation;
+use proc_macro2::{Delimiter, Ident, Spacing, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use venial::{Error, Function};
+
+/// A lazily evaluated return type, whose work is only done after the function
+/// has returned. The values returned by the generic impl and each
+/// specialisation are combined into a single `Specialised` value, which does
+/// that work with the features of the selected specialisation enabled.
+///
+/// Any other opaque return type cannot be combined, so it is always created by
+/// the generic impl, which is called with the features enabled instead.
+pub(crate) enum Adapter {
+    /// An `async fn`, or one returning `impl Future`.
+    Future,
+    /// A function returning `impl Iterator`, `impl DoubleEndedIterator` or
+    /// `impl ExactSizeIterator`.
+    Iterator,
+    /// A function returning `impl Fn(..)`, `impl FnMut(..)` or
+    /// `impl FnOnce(..)`, along with the type of each argument.
+    Closure { kind: Ident, args: Vec<TokenStream> },
+    /// A function returning any other `impl Trait`, such as `impl Display` or
+    /// `Option<impl Iterator>`.
+    Opaque,
+}
+
+impl Adapter {
+    /// Finds the adapter for the return type of the function, which is `None`
+    /// for any return type without `impl Trait`.
+    pub fn parse(orig: &Function) -> Result<Option<Self>, Error> {
+        let tokens = match &orig.return_ty {
+            Some(return_ty) => return_ty.tokens.as_slice(),
+            None => &[],
+        };
+
+        if !contains_impl(tokens) {
+            return Ok(orig.qualifiers.tk_async.is_some().then_some(Self::Future));
+        }
+
+        let error = |message| {
+            Err(Error::new_at_span(
+                orig.return_ty
+                    .as_ref()
+                    .map_or(orig.name.span(), |ty| ty.span()),
+                message,
+            ))
+        };
+
+        // Each specialisation of an async function would return its own type
+        // of output.
+        if orig.qualifiers.tk_async.is_some() {
+            return error("make_special cannot specialise async functions returning impl Trait");
+        }
+
+        let bounds = match tokens {
+            [TokenTree::Ident(ident), bounds @ ..] if ident == "impl" => bounds,
+            _ => return Ok(Some(Self::Opaque)),
+        };
+
+        for bound in split_top_level(bounds, '+') {
+            // Skip any higher-ranked lifetimes, such as `for<'a> Fn(&'a T)`.
+            let bound = match bound.as_slice() {
+                [TokenTree::Ident(ident), rest @ ..] if ident == "for" => {
+                    match rest.iter().position(|token| is_punct(token, '>')) {
+                        Some(end) => &rest[end + 1..],
+                        None => rest,
+                    }
+                }
+                bound => bound,
+            };
+
+            // The name of the trait is the last segment of its path, which is
+            // followed by its generics or the arguments of a closure.
+            let end = bound
+                .iter()
+                .position(|token| is_punct(token, '<') || matches!(token, TokenTree::Group(_)))
+                .unwrap_or(bound.len());
+            let Some(TokenTree::Ident(trait_ident)) = bound[..end].last() else {
+                continue;
+            };
+
+            match trait_ident.to_string().as_str() {
+                "Future" => return Ok(Some(Self::Future)),
+                "Iterator" | "DoubleEndedIterator" | "ExactSizeIterator" => {
+                    return Ok(Some(Self::Iterator));
+                }
+                "Fn" | "FnMut" | "FnOnce" => {
+                    let args = match bound.get(end) {
+                        Some(TokenTree::Group(group))
+                            if group.delimiter() == Delimiter::Parenthesis =>
+                        {
+                            let args: Vec<TokenTree> = group.stream().into_iter().collect();
+                            split_top_level(&args, ',')
+                                .into_iter()
+                                .filter(|arg| !arg.is_empty())
+                                .map(|arg| arg.into_iter().collect())
+                                .collect()
+                        }
+                        _ => return Ok(Some(Self::Opaque)),
+                    };
+
+                    return Ok(Some(Self::Closure {
+                        kind: trait_ident.clone(),
+                        args,
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(Self::Opaque))
+    }
+
+    /// Wraps the value returned by the generic impl (`1`) or a specialisation
+    /// in the variant of `Specialised` for that index.
+    pub fn wrap_value(index: usize, value: TokenStream) -> TokenStream {
+        let variant = format_ident!("V{}", index);
+        quote! { Specialised::#variant(#value) }
+    }
+
+    /// Builds the `Specialised` type, which can hold the value returned by the
+    /// generic impl or any of the given specialisations along with their
+    /// indices. Futures and iterators are polled and advanced with the
+    /// features of the specialisation which created them enabled.
+    pub fn build_def(&self, specs: &[(usize, &Specialisation)]) -> TokenStream {
+        let variant: Vec<Ident> = specs
+            .iter()
+            .map(|(index, _)| format_ident!("V{}", index))
+            .collect();
+        let value_ty: Vec<Ident> = specs
+            .iter()
+            .map(|(index, _)| format_ident!("F{}", index))
+            .collect();
+        let features: Vec<_> = specs
+            .iter()
+            .map(|(_, spec)| spec.enabled_features())
+            .collect();
+        let feature_fn = |prefix: &str| -> Vec<Ident> {
+            specs
+                .iter()
+                .map(|(index, _)| format_ident!("{}_{}", prefix, index))
+                .collect()
+        };
+
+        match self {
+            Self::Future => {
+                let poll_ident = feature_fn("poll");
+
+                quote! {
+                    enum Specialised<F1, #(#value_ty),*> {
+                        V1(F1),
+                        #(#variant(#value_ty),)*
+                    }
+
+                    impl<F1, #(#value_ty),*> ::core::future::Future for Specialised<F1, #(#value_ty),*>
+                    where
+                        F1: ::core::future::Future,
+                        #(#value_ty: ::core::future::Future<Output = F1::Output>,)*
+                    {
+                        type Output = F1::Output;
+
+                        #[inline]
+                        fn poll(
+                            self: ::core::pin::Pin<&mut Self>,
+                            cx: &mut ::core::task::Context<'_>,
+                        ) -> ::core::task::Poll<Self::Output> {
+                            #(
+                                #[target_feature(enable = #features)]
+                                #[inline]
+                                unsafe fn #poll_ident<F: ::core::future::Future>(
+                                    future: ::core::pin::Pin<&mut F>,
+                                    cx: &mut ::core::task::Context<'_>,
+                                ) -> ::core::task::Poll<F::Output> {
+                                    future.poll(cx)
+                                }
+                            )*
+
+                            // SAFETY: The future is pinned along with `self`,
+                            // and the future of a specialisation is only
+                            // created once it has been selected.
+                            unsafe {
+                                match self.get_unchecked_mut() {
+                                    Self::V1(future) => ::core::pin::Pin::new_unchecked(future).poll(cx),
+                                    #(
+                                        Self::#variant(future) => {
+                                            #poll_ident(::core::pin::Pin::new_unchecked(future), cx)
+                                        }
+                                    )*
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Self::Iterator => {
+                let next_ident = feature_fn("next");
+                let next_back_ident = feature_fn("next_back");
+                let fold_ident = feature_fn("fold");
+
+                // The adapters of `Iterator` are built on `next` and `fold`,
+                // so both run with the features enabled. Any other traits the
+                // iterators share are implemented when they apply.
+                quote! {
+                    #[derive(Clone, Copy, Debug)]
+                    enum Specialised<F1, #(#value_ty),*> {
+                        V1(F1),
+                        #(#variant(#value_ty),)*
+                    }
+
+                    impl<F1, #(#value_ty),*> ::core::iter::Iterator for Specialised<F1, #(#value_ty),*>
+                    where
+                        F1: ::core::iter::Iterator,
+                        #(#value_ty: ::core::iter::Iterator<Item = F1::Item>,)*
+                    {
+                        type Item = F1::Item;
+
+                        #[inline]
+                        fn next(&mut self) -> ::core::option::Option<Self::Item> {
+                            #(
+                                #[target_feature(enable = #features)]
+                                #[inline]
+                                unsafe fn #next_ident<I: ::core::iter::Iterator>(
+                                    iter: &mut I,
+                                ) -> ::core::option::Option<I::Item> {
+                                    iter.next()
+                                }
+                            )*
+
+                            // SAFETY: The iterator of a specialisation is
+                            // only created once it has been selected.
+                            match self {
+                                Self::V1(iter) => iter.next(),
+                                #(Self::#variant(iter) => unsafe { #next_ident(iter) },)*
+                            }
+                        }
+
+                        #[inline]
+                        fn size_hint(&self) -> (usize, ::core::option::Option<usize>) {
+                            match self {
+                                Self::V1(iter) => iter.size_hint(),
+                                #(Self::#variant(iter) => iter.size_hint(),)*
+                            }
+                        }
+
+                        #[inline]
+                        fn fold<B, G>(self, init: B, f: G) -> B
+                        where
+                            G: ::core::ops::FnMut(B, Self::Item) -> B,
+                        {
+                            #(
+                                #[target_feature(enable = #features)]
+                                #[inline]
+                                unsafe fn #fold_ident<I, B, G>(iter: I, init: B, f: G) -> B
+                                where
+                                    I: ::core::iter::Iterator,
+                                    G: ::core::ops::FnMut(B, I::Item) -> B,
+                                {
+                                    iter.fold(init, f)
+                                }
+                            )*
+
+                            // SAFETY: See `next`.
+                            match self {
+                                Self::V1(iter) => iter.fold(init, f),
+                                #(Self::#variant(iter) => unsafe { #fold_ident(iter, init, f) },)*
+                            }
+                        }
+                    }
+
+                    impl<F1, #(#value_ty),*> ::core::iter::DoubleEndedIterator for Specialised<F1, #(#value_ty),*>
+                    where
+                        F1: ::core::iter::DoubleEndedIterator,
+                        #(#value_ty: ::core::iter::DoubleEndedIterator<Item = F1::Item>,)*
+                    {
+                        #[inline]
+                        fn next_back(&mut self) -> ::core::option::Option<Self::Item> {
+                            #(
+                                #[target_feature(enable = #features)]
+                                #[inline]
+                                unsafe fn #next_back_ident<I: ::core::iter::DoubleEndedIterator>(
+                                    iter: &mut I,
+                                ) -> ::core::option::Option<I::Item> {
+                                    iter.next_back()
+                                }
+                            )*
+
+                            // SAFETY: See `Iterator::next`.
+                            match self {
+                                Self::V1(iter) => iter.next_back(),
+                                #(Self::#variant(iter) => unsafe { #next_back_ident(iter) },)*
+                            }
+                        }
+                    }
+
+                    impl<F1, #(#value_ty),*> ::core::iter::ExactSizeIterator for Specialised<F1, #(#value_ty),*>
+                    where
+                        F1: ::core::iter::ExactSizeIterator,
+                        #(#value_ty: ::core::iter::ExactSizeIterator<Item = F1::Item>,)*
+                    {
+                    }
+
+                    impl<F1, #(#value_ty),*> ::core::iter::FusedIterator for Specialised<F1, #(#value_ty),*>
+                    where
+                        F1: ::core::iter::FusedIterator,
+                        #(#value_ty: ::core::iter::FusedIterator<Item = F1::Item>,)*
+                    {
+                    }
+                }
+            }
+            // Closures can only be wrapped in another closure, which is built
+            // by `build_closure` once the value has been selected.
+            Self::Closure { .. } => quote! {
+                #[derive(Clone, Copy)]
+                enum Specialised<F1, #(#value_ty),*> {
+                    V1(F1),
+                    #(#variant(#value_ty),)*
+                }
+            },
+            // Every specialisation returns the value of the generic impl, so
+            // there is nothing to combine.
+            Self::Opaque => quote! {},
+        }
+    }
+
+    /// Builds the closure returned instead of the `Specialised` value for a
+    /// [`Closure`](Self::Closure) adapter, which calls the selected closure
+    /// with the features of its specialisation enabled. `select` is a block
+    /// labelled `'value` which evaluates to the `Specialised` value.
+    pub fn build_closure(
+        kind: &Ident,
+        args: &[TokenStream],
+        specs: &[(usize, &Specialisation)],
+        select: TokenStream,
+    ) -> TokenStream {
+        let variant = specs.iter().map(|(index, _)| format_ident!("V{}", index));
+        let features = specs.iter().map(|(_, spec)| spec.enabled_features());
+        let call_ident: Vec<Ident> = specs
+            .iter()
+            .map(|(index, _)| format_ident!("call_{}", index))
+            .collect();
+        let arg_ident: Vec<Ident> = (0..args.len()).map(|i| format_ident!("arg{}", i)).collect();
+        let arg_ty_ident: Vec<Ident> = (0..args.len()).map(|i| format_ident!("A{}", i)).collect();
+        let arg_names = quote! { #(#arg_ident),* };
+        let arg_tys = quote! { #(#arg_ty_ident),* };
+        let arg_generics = quote! { #(#arg_ty_ident,)* };
+        let arg_params = quote! { #(#arg_ident: #arg_ty_ident),* };
+
+        // The caller is borrowed in the same way as the closure it returns.
+        let (func_ty, func_ref, tk_mut) = match kind.to_string().as_str() {
+            "Fn" => (quote! { &F }, quote! { & }, None),
+            "FnMut" => (quote! { &mut F }, quote! { &mut }, Some(quote! { mut })),
+            _ => (quote! { F }, quote! {}, None),
+        };
+
+        // The callers cannot name any generics of the function, so they are
+        // generic over the arguments instead.
+        quote! {
+            #(
+                #[target_feature(enable = #features)]
+                #[inline]
+                unsafe fn #call_ident<F, #arg_generics R>(func: #func_ty, #arg_params) -> R
+                where
+                    F: ::core::ops::#kind(#arg_tys) -> R,
+                {
+                    func(#arg_names)
+                }
+            )*
+
+            let #tk_mut specialised = 'value: #select;
+
+            // SAFETY: The closure of a specialisation is only created once it
+            // has been selected.
+            move |#(#arg_ident: #args),*| match #func_ref specialised {
+                Specialised::V1(func) => func(#arg_names),
+                #(Specialised::#variant(func) => unsafe { #call_ident(func, #arg_names) },)*
+            }
+        }
+    }
+}
+
+fn contains_impl(tokens: &[TokenTree]) -> bool {
+    tokens.iter().any(|token| match token {
+        TokenTree::Ident(ident) => ident == "impl",
+        TokenTree::Group(group) => contains_impl(&group.stream().into_iter().collect::<Vec<_>>()),
+        _ => false,
+    })
+}
+
+fn is_punct(token: &TokenTree, ch: char) -> bool {
+    matches!(token, TokenTree::Punct(punct) if punct.as_char() == ch)
+}
+
+/// Splits the tokens of a type at each `separator` which is not nested inside
+/// of generics.
+fn split_top_level(tokens: &[TokenTree], separator: char) -> Vec<Vec<TokenTree>> {
+    let mut parts = vec![];
+    let mut part = vec![];
+    let mut depth = 0usize;
+    let mut after_arrow_start = false;
+
+    for token in tokens {
+        let is_arrow_start = matches!(
+            token,
+            TokenTree::Punct(punct) if punct.as_char() == '-' && punct.spacing() == Spacing::Joint
+        );
+
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '<' => depth += 1,
+            // The `>` of `->` does not close any generics.
+            TokenTree::Punct(punct) if punct.as_char() == '>' && !after_arrow_start => {
+                depth = depth.saturating_sub(1);
+            }
+            TokenTree::Punct(punct) if punct.as_char() == separator && depth == 0 => {
+                parts.push(std::mem::take(&mut part));
+                after_arrow_start = false;
+                continue;
+            }
+            _ => {}
+        }
+
+        after_arrow_start = is_arrow_start;
+        part.push(token.clone());
+    }
+
+    parts.push(part);
+    parts
+}
@@ -0,0 +1,241 @@
+use crate::{features, options::Detect};
+use proc_macro2::{Ident, Literal, TokenStream, TokenTree};
+use quote::{ToTokens, TokenStreamExt, format_ident, quote};
+use std::str::FromStr;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Architecture {
+    AARCH64,
+    LOONGARCH64,
+    LOONGARCH32,
+    RISCV,
+    X86,
+    ARM,
+    MIPS64,
+    MIPS32,
+    POWERPC64,
+    POWERPC32,
+    S390X,
+    WASM,
+    SPARC64,
+    CSKY,
+    HEXAGON,
+    M68K,
+    BPF,
+}
+
+impl Architecture {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AARCH64 => "aarch64",
+            Self::LOONGARCH64 => "loongarch64",
+            Self::LOONGARCH32 => "loongarch32",
+            Self::RISCV => "riscv",
+            Self::X86 => "x86",
+            Self::ARM => "arm",
+            Self::MIPS64 => "mips64",
+            Self::MIPS32 => "mips",
+            Self::POWERPC64 => "powerpc64",
+            Self::POWERPC32 => "powerpc",
+            Self::S390X => "s390x",
+            Self::WASM => "wasm",
+            Self::SPARC64 => "sparc64",
+            Self::CSKY => "csky",
+            Self::HEXAGON => "hexagon",
+            Self::M68K => "m68k",
+            Self::BPF => "bpf",
+        }
+    }
+
+    pub fn cfg_inner(&self) -> TokenStream {
+        match self {
+            Self::X86 => quote! { any(target_arch = "x86", target_arch = "x86_64") },
+            Self::RISCV => quote! { any(target_arch = "riscv32", target_arch = "riscv64") },
+            // Arm64EC uses the same features and run-time detection as AArch64.
+            Self::AARCH64 => quote! { any(target_arch = "aarch64", target_arch = "arm64ec") },
+            Self::MIPS32 => quote! { any(target_arch = "mips", target_arch = "mips32r6") },
+            Self::MIPS64 => quote! { any(target_arch = "mips64", target_arch = "mips64r6") },
+            Self::WASM => quote! { any(target_arch = "wasm32", target_arch = "wasm64") },
+            other => quote! { target_arch = #other },
+        }
+    }
+
+    pub fn dispatch_ident(&self) -> Ident {
+        format_ident!("_dispatch_{}", self.as_str())
+    }
+
+    pub fn jump_ref_ident(&self) -> Ident {
+        format_ident!("JUMP_REF_{}", &self.as_str())
+    }
+
+    pub fn init_ident(&self) -> Ident {
+        format_ident!("_init_{}", self.as_str())
+    }
+
+    pub fn select_ident(&self) -> Ident {
+        format_ident!("_select_{}", self.as_str())
+    }
+
+    pub fn detect_macro(&self) -> Ident {
+        match self {
+            Self::LOONGARCH64 | Self::LOONGARCH32 => format_ident!("is_loongarch_feature_detected"),
+            other => format_ident!("is_{}_feature_detected", other.as_str()),
+        }
+    }
+
+    /// Whether this architecture has no run-time feature detection, so its
+    /// specialisations can only use static dispatch.
+    pub fn is_static_only(&self) -> bool {
+        matches!(
+            self,
+            Self::WASM | Self::SPARC64 | Self::CSKY | Self::HEXAGON | Self::M68K | Self::BPF
+        )
+    }
+
+    /// The target features which can be detected at run-time on this
+    /// architecture, or which can be enabled at compile-time if it is
+    /// [static only](Self::is_static_only).
+    pub fn known_features(&self) -> &'static [&'static str] {
+        match self {
+            Self::AARCH64 => features::AARCH64,
+            Self::LOONGARCH64 | Self::LOONGARCH32 => features::LOONGARCH,
+            Self::RISCV => features::RISCV,
+            Self::X86 => features::X86,
+            Self::ARM => features::ARM,
+            Self::MIPS64 | Self::MIPS32 => features::MIPS,
+            Self::POWERPC64 | Self::POWERPC32 => features::POWERPC,
+            Self::S390X => features::S390X,
+            Self::WASM => features::WASM,
+            Self::SPARC64 => features::SPARC64,
+            Self::CSKY => features::CSKY,
+            Self::HEXAGON => features::HEXAGON,
+            Self::M68K => features::M68K,
+            Self::BPF => features::BPF,
+        }
+    }
+
+    /// Detects whether a feature is available at run-time, using the custom
+    /// backend if one was given, or `cpuid` on x86 and the auxiliary vector on
+    /// AArch64 and RISC-V without `std` if the `cpuid` or `auxv` features are
+    /// enabled. Features are looked up in the shared cache,
+    /// so that each of them is only detected once for the entire binary.
+    pub(crate) fn detect_feature(&self, feature: &str, backend: Option<&Detect>) -> TokenStream {
+        let detect = match backend {
+            Some(backend) => backend.call(feature),
+            None if *self == Self::X86 && cfg!(feature = "cpuid") && !cfg!(feature = "std") => {
+                quote! { ::maybe_special::cpuid::detect(#feature) }
+            }
+            None if self.uses_auxv(backend) => {
+                quote! { ::maybe_special::auxv::detect(#feature) }
+            }
+            None => {
+                let prefix = if cfg!(feature = "std") {
+                    quote! { ::std::arch:: }
+                } else {
+                    quote! { ::std_detect:: }
+                };
+
+                let detect_macro = self.detect_macro();
+                quote! { #prefix #detect_macro !(#feature) }
+            }
+        };
+
+        // A feature cannot be used once any feature it implies is disabled.
+        let mut implied: Vec<&str> = self
+            .implied_features([feature])
+            .into_iter()
+            .filter(|implied| *implied != feature)
+            .collect();
+        implied.sort_unstable();
+
+        match self.feature_index(feature) {
+            Some(index) => quote! {
+                ::maybe_special::__private::FEATURES.test(#index, #feature, &[#(#implied),*], || #detect)
+            },
+            None => quote! {
+                ::maybe_special::__private::detect(#feature, &[#(#implied),*], || #detect)
+            },
+        }
+    }
+
+    /// Whether features are detected through the auxiliary vector, which is
+    /// the case on AArch64 and RISC-V without `std` if the `auxv` feature is
+    /// enabled and no custom backend was given.
+    pub(crate) fn uses_auxv(&self, backend: Option<&Detect>) -> bool {
+        backend.is_none()
+            && matches!(self, Self::AARCH64 | Self::RISCV)
+            && cfg!(feature = "auxv")
+            && !cfg!(feature = "std")
+    }
+
+    /// The known features which the kernel does not report through the
+    /// auxiliary vector or `riscv_hwprobe`, so they are never detected by the
+    /// `auxv` backend.
+    pub fn auxv_undetectable(&self) -> &'static [&'static str] {
+        match self {
+            Self::AARCH64 => &["tme", "pauth-lr"],
+            Self::RISCV => &[
+                "rv32e", "rv128i", "zam", "q", "h", "s", "zfinx", "zdinx", "zhinx", "zhinxmin",
+                "zkr", "zk", "svnapot", "svpbmt", "svinval", "sstc",
+            ],
+            _ => &[],
+        }
+    }
+
+    /// Returns the index of a feature in the shared feature cache.
+    pub fn feature_index(&self, feature: &str) -> Option<usize> {
+        self.known_features()
+            .iter()
+            .position(|known| *known == feature)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnimplementedArch;
+
+impl FromStr for Architecture {
+    type Err = UnimplementedArch;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "aarch64" => Architecture::AARCH64,
+            "arm64ec" => Architecture::AARCH64,
+            "loongarch" => Architecture::LOONGARCH64,
+            "loongarch64" => Architecture::LOONGARCH64,
+            "loongarch32" => Architecture::LOONGARCH32,
+            "riscv" => Architecture::RISCV,
+            "x86" => Architecture::X86,
+            "x86_64" => Architecture::X86,
+            "arm" => Architecture::ARM,
+            "mips64" => Architecture::MIPS64,
+            "mips32" => Architecture::MIPS32,
+            "mips" => Architecture::MIPS32,
+            "powerpc64" => Architecture::POWERPC64,
+            "powerpc32" => Architecture::POWERPC32,
+            "powerpc" => Architecture::POWERPC32,
+            "s390x" => Architecture::S390X,
+            "wasm" => Architecture::WASM,
+            "wasm32" => Architecture::WASM,
+            "wasm64" => Architecture::WASM,
+            "sparc64" => Architecture::SPARC64,
+            "csky" => Architecture::CSKY,
+            "hexagon" => Architecture::HEXAGON,
+            "m68k" => Architecture::M68K,
+            "bpf" => Architecture::BPF,
+            _ => return Err(UnimplementedArch),
+        })
+    }
+}
+
+impl From<Architecture> for &str {
+    fn from(value: Architecture) -> Self {
+        value.as_str()
+    }
+}
+
+impl ToTokens for Architecture {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.append(TokenTree::Literal(Literal::string(self.as_str())));
+    }
+}
@@ -0,0 +1,757 @@
+use crate::{
+    adapter::Adapter,
+    generic_ident,
+    r#macro::is_cfg_attribute,
+    options::{Callee, Options},
+    typed_self_ident,
+};
+use proc_macro2::{Delimiter, Group, Ident, Spacing, Span, TokenStream, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use venial::{Attribute, Error, FnParam, FnReceiverParam, FnTypedParam, Function, Punctuated};
+
+/// Where the specialised function is defined, which decides where its
+/// generated items are placed and how they are called.
+pub(crate) enum Scope {
+    /// A module or block, where the items are placed next to the function.
+    Free,
+    /// An inherent `impl`, where the items are associated functions.
+    Impl,
+    /// A trait `impl`, where the items are methods of the given hidden helper
+    /// trait, which is also implemented for `Self`.
+    TraitImpl(TokenStream),
+}
+
+pub(crate) struct FnBuilder<'a> {
+    orig: &'a Function,
+    pub use_jump_table: bool,
+    pub is_generic: bool,
+    /// Whether the function is defined in an `impl`, or refers to `Self`.
+    pub is_method: bool,
+    /// The helper trait containing the generated items of a function inside of
+    /// a trait `impl`.
+    helper_trait: Option<TokenStream>,
+    /// The name the feature token is bound to inside of the generic impl.
+    token: Option<Ident>,
+    /// The macros defining a copy of each helper listed in `callees = [...]`.
+    pub callees: Vec<TokenStream>,
+    /// How the values returned by each specialisation are combined, when the
+    /// function returns a future, iterator or closure which does its work
+    /// after it returns.
+    pub adapter: Option<Adapter>,
+    inner_params: TokenStream,
+    outer_params: TokenStream,
+    pub param_idents: TokenStream,
+    param_tys: TokenStream,
+    inner_return_ty: TokenStream,
+    outer_return_ty: TokenStream,
+}
+
+impl<'a> FnBuilder<'a> {
+    pub fn new(orig: &'a Function, scope: &Scope, options: &Options) -> Result<Self, Error> {
+        // Every specialisation returns its own type of future, iterator or
+        // closure, which cannot be named by a function pointer.
+        let adapter = Adapter::parse(orig)?;
+        let mut use_jump_table = adapter.is_some();
+
+        // Each token would be passed into its own instance of the generic impl,
+        // which returns its own opaque type.
+        if let (Some(Adapter::Opaque), Some(token)) = (&adapter, &options.token) {
+            return Err(Error::new_at_span(
+                token.span(),
+                "a feature token cannot be used on functions returning this impl Trait",
+            ));
+        }
+        let mut is_generic = false;
+        let mut is_method = false;
+        let mut inner_params = Punctuated {
+            inner: vec![],
+            skip_last: true,
+        };
+        let mut outer_params = Punctuated {
+            inner: vec![],
+            skip_last: true,
+        };
+        let mut param_idents = Punctuated {
+            inner: vec![],
+            skip_last: true,
+        };
+        let mut param_tys = Punctuated {
+            inner: vec![],
+            skip_last: true,
+        };
+
+        for (param, _) in orig.params.iter() {
+            let param = match param {
+                FnParam::Receiver(rec_param) => {
+                    is_method = true;
+
+                    let FnReceiverParam {
+                        tk_ref,
+                        lifetime,
+                        tk_mut,
+                        tk_self,
+                        ..
+                    } = rec_param;
+
+                    // `mut self` only affects the binding inside the generic impl, however
+                    // `&mut self` is part of the receiver type.
+                    let tk_mut = tk_ref.as_ref().and(tk_mut.clone());
+
+                    inner_params.push(param.clone(), None);
+                    outer_params.push(
+                        FnParam::Receiver(FnReceiverParam {
+                            attributes: rec_param.attributes.clone(),
+                            tk_ref: tk_ref.clone(),
+                            lifetime: lifetime.clone(),
+                            tk_mut: tk_mut.clone(),
+                            tk_self: tk_self.clone(),
+                        }),
+                        None,
+                    );
+                    param_idents.push(tk_self.clone(), None);
+                    param_tys.push(quote! { #tk_ref #lifetime #tk_mut Self }, None);
+                    continue;
+                }
+                FnParam::Typed(param) => param,
+            };
+
+            if param
+                .ty
+                .tokens
+                .iter()
+                .any(|token| matches!(token, TokenTree::Ident(ident) if *ident == "impl"))
+            {
+                is_generic = true;
+            }
+
+            let name = if param.name == typed_self_ident() {
+                is_method = true;
+                Ident::new("self", param.name.span())
+            } else {
+                param.name.clone()
+            };
+
+            inner_params.push(
+                FnParam::Typed(FnTypedParam {
+                    name: name.clone(),
+                    ..param.clone()
+                }),
+                None,
+            );
+            outer_params.push(
+                FnParam::Typed(FnTypedParam {
+                    attributes: param.attributes.clone(),
+                    tk_mut: None,
+                    name: name.clone(),
+                    tk_colon: param.tk_colon.clone(),
+                    ty: param.ty.clone(),
+                }),
+                None,
+            );
+            param_idents.push(name, None);
+            param_tys.push(param.ty.to_token_stream(), None);
+        }
+
+        if let Some(generics) = &orig.generic_params {
+            for (generic, _) in generics.params.iter() {
+                if !generic
+                    .tk_prefix
+                    .as_ref()
+                    .is_some_and(|tk_prefix| matches!(tk_prefix, TokenTree::Punct(_)))
+                {
+                    is_generic = true;
+                    break;
+                }
+            }
+        }
+
+        if !is_method {
+            is_method = !matches!(scope, Scope::Free);
+        }
+
+        if !is_method {
+            is_method = [
+                orig.generic_params.to_token_stream(),
+                orig.params.to_token_stream(),
+                orig.return_ty.to_token_stream(),
+                orig.where_clause.to_token_stream(),
+                orig.body.to_token_stream(),
+            ]
+            .into_iter()
+            .any(contains_self_ty);
+        }
+
+        if let Some(body) = &orig.body {
+            check_callee_calls(body.stream(), &options.callees)?;
+        }
+
+        use_jump_table |= is_generic;
+
+        // Statics cannot be associated items, so they have to live inside a
+        // function body, where they are shared by every instantiation of the
+        // `impl`. Only an index is safe to share between them.
+        use_jump_table |= is_method;
+
+        // A function pointer caches the selected specialisation, which cannot
+        // be done when it may be overridden.
+        use_jump_table |= cfg!(feature = "override");
+
+        let inner_return_ty = orig
+            .return_ty
+            .as_ref()
+            .map(|return_ty| return_ty.to_token_stream())
+            .unwrap_or(quote! {()});
+
+        let outer_return_ty = if orig.qualifiers.tk_async.is_some() {
+            quote! { impl ::core::future::Future<Output = #inner_return_ty> }
+        } else {
+            inner_return_ty.clone()
+        };
+
+        Ok(Self {
+            orig,
+            use_jump_table,
+            is_generic,
+            is_method,
+            helper_trait: match scope {
+                Scope::TraitImpl(helper_trait) => Some(helper_trait.clone()),
+                _ => None,
+            },
+            token: options.token.clone(),
+            callees: options
+                .callees
+                .iter()
+                .map(|callee| callee.clonable.clone())
+                .collect(),
+            adapter,
+            inner_params: inner_params.into_token_stream(),
+            outer_params: outer_params.into_token_stream(),
+            param_idents: param_idents.into_token_stream(),
+            param_tys: param_tys.into_token_stream(),
+            inner_return_ty,
+            outer_return_ty,
+        })
+    }
+
+    /// Returns the name of a generated item. These items are placed next to
+    /// the function, so they are prefixed with its name to keep them unique
+    /// within the module or `impl`.
+    pub fn inner_ident(&self, ident: Ident) -> Ident {
+        let name = self.orig.name.to_string();
+        format_ident!("_{}{}", name.trim_start_matches("r#"), ident)
+    }
+
+    pub fn generic_ident(&self) -> Ident {
+        self.inner_ident(generic_ident())
+    }
+
+    /// The generic impl which takes a feature token, only used when
+    /// [`uses_token`](Self::uses_token) is set.
+    fn token_generic_ident(&self) -> Ident {
+        self.inner_ident(Ident::new("_generic_with_token", Span::call_site()))
+    }
+
+    /// Whether the generic impl takes a feature token. Functions with an
+    /// [`adapter`](Self::adapter) always take one, so that every
+    /// specialisation returns its own type of future, iterator or closure,
+    /// which only runs with the features of that specialisation enabled. An
+    /// [`Opaque`](Adapter::Opaque) return type is the exception, as every
+    /// specialisation has to return the same value as the generic impl.
+    pub fn uses_token(&self) -> bool {
+        self.token.is_some()
+            || self
+                .adapter
+                .as_ref()
+                .is_some_and(|adapter| !matches!(adapter, Adapter::Opaque))
+    }
+
+    /// Binds the feature token to the name given by `token = name`.
+    pub fn bind_token(&self, value: TokenStream) -> TokenStream {
+        match &self.token {
+            Some(token) => quote! { let #token = #value; },
+            None => quote! {},
+        }
+    }
+
+    /// The `#[cfg]` attributes of the original function, which must also be
+    /// applied to any item generated next to it.
+    pub fn cfg_attributes(&self) -> impl Iterator<Item = &Attribute> {
+        self.orig
+            .attributes
+            .iter()
+            .filter(|attribute| is_cfg_attribute(attribute))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        &self,
+        attributes: &[TokenStream],
+        copy_async: bool,
+        copy_const: bool,
+        tk_unsafe: &Option<Ident>,
+        name: &Ident,
+        params: &TokenStream,
+        return_ty: &TokenStream,
+        body: TokenStream,
+    ) -> TokenStream {
+        let tk_async = if copy_async {
+            &self.orig.qualifiers.tk_async
+        } else {
+            &None
+        };
+
+        let tk_const = if copy_const {
+            &self.orig.qualifiers.tk_const
+        } else {
+            &None
+        };
+
+        let tk_extern = &self.orig.qualifiers.tk_extern;
+        let extern_abi = &self.orig.qualifiers.extern_abi;
+        let generics = &self.orig.generic_params;
+        let where_clause = &self.orig.where_clause;
+
+        quote! {
+            #(#[#attributes])*
+            #tk_const #tk_async #tk_unsafe #tk_extern #extern_abi
+            fn #name #generics (#params) -> #return_ty #where_clause { #body }
+        }
+    }
+
+    pub fn build_detail(
+        &self,
+        attributes: &[TokenStream],
+        copy_const: bool,
+        copy_unsafe: bool,
+        name: &Ident,
+        body: TokenStream,
+    ) -> TokenStream {
+        self.build(
+            attributes,
+            false, //copy_async
+            copy_const,
+            if copy_unsafe {
+                &self.orig.qualifiers.tk_unsafe
+            } else {
+                &None
+            },
+            name,
+            &self.outer_params,
+            &self.outer_return_ty,
+            body,
+        )
+    }
+
+    /// Builds a specialisation clone. `#[target_feature]` can only be applied
+    /// to unsafe trait methods, so clones inside of a trait are always unsafe.
+    pub fn build_clone(
+        &self,
+        attributes: &[TokenStream],
+        name: &Ident,
+        body: TokenStream,
+    ) -> TokenStream {
+        self.build(
+            attributes,
+            false, //copy_async
+            true,  //copy_const
+            &self.clone_unsafe(),
+            name,
+            &self.outer_params,
+            &self.outer_return_ty,
+            body,
+        )
+    }
+
+    /// Builds the specialisation of a function with an
+    /// [`Opaque`](Adapter::Opaque) return type. Each specialisation would
+    /// return its own opaque type, so it only enables its features for a call
+    /// to the generic impl, which is always inlined into it.
+    pub fn build_trampoline(&self, attributes: &[TokenStream], name: &Ident) -> TokenStream {
+        quote! {
+            #(#[#attributes])*
+            unsafe fn #name<R>(f: impl ::core::ops::FnOnce() -> R) -> R { f() }
+        }
+    }
+
+    /// Builds a specialisation clone containing its own copy of the original
+    /// body, which runs after `prelude`, so that items defined by the prelude
+    /// shadow those outside of the function.
+    pub fn build_inline_clone(
+        &self,
+        attributes: &[TokenStream],
+        name: &Ident,
+        prelude: TokenStream,
+    ) -> TokenStream {
+        let body = self.body();
+        let body = if self.orig.qualifiers.tk_async.is_some() {
+            quote! { async move { #body } }
+        } else {
+            quote! { { #body } }
+        };
+
+        self.build(
+            attributes,
+            false, //copy_async
+            true,  //copy_const
+            &self.clone_unsafe(),
+            name,
+            &self.inner_params,
+            &self.outer_return_ty,
+            quote! {
+                #prelude
+                #body
+            },
+        )
+    }
+
+    /// Whether the function is inside of a trait `impl`.
+    pub fn in_trait(&self) -> bool {
+        self.helper_trait.is_some()
+    }
+
+    fn clone_unsafe(&self) -> Option<Ident> {
+        if self.in_trait() {
+            Some(Ident::new("unsafe", Span::call_site()))
+        } else {
+            self.orig.qualifiers.tk_unsafe.clone()
+        }
+    }
+
+    fn body(&self) -> TokenStream {
+        match &self.orig.body {
+            Some(body) => body.stream(),
+            None => {
+                Error::new("make_special cannot take fn items without a body").to_compile_error()
+            }
+        }
+    }
+
+    /// Builds the generic impl. With a feature token, the body is moved into a
+    /// separate function taking the token as its last parameter, so each
+    /// specialisation can pass in its own token, and the generic impl passes in
+    /// a `GenericToken`.
+    pub fn build_generic(&self) -> Vec<TokenStream> {
+        let body = self.body();
+        if !self.uses_token() {
+            return vec![self.build(
+                &[quote!(inline(always))],
+                true, //copy_async
+                true, //copy_const
+                &self.orig.qualifiers.tk_unsafe,
+                &self.generic_ident(),
+                &self.inner_params,
+                &self.inner_return_ty,
+                body,
+            )];
+        };
+
+        let token = match &self.token {
+            Some(token) => token.to_token_stream(),
+            None => quote! { _ },
+        };
+        let inner_params = &self.inner_params;
+        let token_param = quote! { #token: impl ::maybe_special::Token };
+        let token_params = if inner_params.is_empty() {
+            token_param
+        } else {
+            quote! { #inner_params, #token_param }
+        };
+
+        vec![
+            self.build(
+                &[quote!(inline(always))],
+                true, //copy_async
+                true, //copy_const
+                &self.orig.qualifiers.tk_unsafe,
+                &self.token_generic_ident(),
+                &token_params,
+                &self.inner_return_ty,
+                body,
+            ),
+            self.build(
+                &[quote!(inline(always))],
+                false, //copy_async
+                true,  //copy_const
+                &self.orig.qualifiers.tk_unsafe,
+                &self.generic_ident(),
+                &self.outer_params,
+                &self.outer_return_ty,
+                self.build_token_call(quote! { ::maybe_special::GenericToken }),
+            ),
+        ]
+    }
+
+    /// Builds a call to the generic impl which passes in the given feature
+    /// token.
+    pub fn build_token_call(&self, token: TokenStream) -> TokenStream {
+        let tk_unsafe = &self.orig.qualifiers.tk_unsafe;
+        let path = self.build_path(&self.token_generic_ident());
+        let param_idents = &self.param_idents;
+        let args = if param_idents.is_empty() {
+            token
+        } else {
+            quote! { #param_idents, #token }
+        };
+
+        quote! { #tk_unsafe { #path(#args) } }
+    }
+
+    /// The types of every parameter as a tuple.
+    pub fn build_param_tuple(&self) -> TokenStream {
+        let param_tys = &self.param_tys;
+        if param_tys.is_empty() {
+            quote! { () }
+        } else {
+            quote! { (#param_tys,) }
+        }
+    }
+
+    /// A pattern binding every parameter from a tuple.
+    pub fn build_param_pattern(&self) -> TokenStream {
+        let param_idents = &self.param_idents;
+        if param_idents.is_empty() {
+            quote! { () }
+        } else {
+            quote! { (#param_idents,) }
+        }
+    }
+
+    pub fn build_ptr(&self) -> TokenStream {
+        let tk_unsafe = &self.orig.qualifiers.tk_unsafe;
+        let tk_extern = &self.orig.qualifiers.tk_extern;
+        let extern_abi = &self.orig.qualifiers.extern_abi;
+        let param_tys = &self.param_tys;
+        let return_ty = &self.outer_return_ty;
+        let lifetimes = self.orig.generic_params.iter().flat_map(|generics| {
+            generics
+                .params
+                .iter()
+                .filter(|(param, _)| param.is_lifetime())
+                .map(|(param, _)| param.into_token_stream())
+        });
+
+        quote! { for<#(#lifetimes),*> #tk_unsafe #tk_extern #extern_abi fn(#param_tys) -> #return_ty }
+    }
+
+    /// Builds the `resolve` accessor, which returns the selected specialisation
+    /// as a function pointer, or as a copyable closure when jump table dispatch
+    /// is used. Returns `None` when the dispatcher cannot be named, or would
+    /// hide that the function is unsafe.
+    pub fn build_resolve(&self, name: &Ident, body: TokenStream) -> Option<TokenStream> {
+        if self.adapter.is_some() {
+            return None;
+        }
+
+        if !self.use_jump_table {
+            let fn_ptr = self.build_ptr();
+            return Some(quote! {
+                fn #name() -> #fn_ptr { #body }
+            });
+        }
+
+        let has_impl_param = self
+            .param_tys
+            .clone()
+            .into_iter()
+            .any(|token| matches!(token, TokenTree::Ident(ident) if ident == "impl"));
+        if self.orig.qualifiers.tk_unsafe.is_some() || has_impl_param {
+            return None;
+        }
+
+        // Lifetimes are bound by the closure signature instead.
+        let generics = self.orig.generic_params.iter().flat_map(|generics| {
+            generics
+                .params
+                .iter()
+                .filter(|(param, _)| !param.is_lifetime())
+                .map(|(param, _)| param.into_token_stream())
+        });
+        let lifetimes = self.orig.generic_params.iter().flat_map(|generics| {
+            generics
+                .params
+                .iter()
+                .filter(|(param, _)| param.is_lifetime())
+                .map(|(param, _)| param.into_token_stream())
+        });
+        let param_tys = &self.param_tys;
+        let return_ty = &self.outer_return_ty;
+        // Trait methods taking `self` by value only require `Self: Sized` in
+        // their own signature, but the closure type needs it up front.
+        let where_clause = match (&self.orig.where_clause, self.in_trait()) {
+            (Some(where_clause), true) => {
+                let predicates = where_clause.items.iter().map(|(predicate, _)| predicate);
+                quote! { where #(#predicates,)* Self: ::core::marker::Sized }
+            }
+            (None, true) => quote! { where Self: ::core::marker::Sized },
+            (where_clause, false) => where_clause.to_token_stream(),
+        };
+
+        Some(quote! {
+            fn #name<#(#generics),*>()
+                -> impl for<#(#lifetimes),*> ::core::ops::Fn(#param_tys) -> #return_ty + ::core::marker::Copy
+            #where_clause
+            { #body }
+        })
+    }
+
+    /// The names of the parameters of a closure taking the same arguments as
+    /// the function. Closures cannot take `self`, so it is renamed.
+    pub fn closure_param_idents(&self) -> TokenStream {
+        rename_self(self.param_idents.clone())
+    }
+
+    /// Builds the path to a generated item. Items inside of a helper trait are
+    /// called through it, as `Self::` is ambiguous when the trait has generics
+    /// which the item does not use.
+    pub fn build_path(&self, ident: &Ident) -> TokenStream {
+        match &self.helper_trait {
+            Some(helper_trait) => quote! { <Self as #helper_trait>::#ident },
+            None if self.is_method => quote! { Self::#ident },
+            None => ident.to_token_stream(),
+        }
+    }
+
+    pub fn build_call(&self, ident: &Ident) -> TokenStream {
+        let tk_unsafe = &self.orig.qualifiers.tk_unsafe;
+        let path = self.build_path(ident);
+        let param_idents = &self.param_idents;
+        quote! { #tk_unsafe { #path(#param_idents) } }
+    }
+
+    /// Builds a call to a specialisation with the given arguments. The
+    /// specialisations of a function with an [`Opaque`](Adapter::Opaque) return
+    /// type only enable their features, so they are given a call to the
+    /// generic impl instead.
+    pub fn build_spec_call(&self, ident: &Ident, args: &TokenStream) -> TokenStream {
+        let path = self.build_path(ident);
+        match self.adapter {
+            Some(Adapter::Opaque) => {
+                let tk_unsafe = &self.orig.qualifiers.tk_unsafe;
+                let generic_path = self.build_path(&self.generic_ident());
+                quote! { unsafe { #path(move || #tk_unsafe { #generic_path(#args) }) } }
+            }
+            _ => quote! { unsafe { #path(#args) } },
+        }
+    }
+}
+
+fn rename_self(tokens: TokenStream) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Ident(ident) if ident == "self" => TokenTree::Ident(typed_self_ident()),
+            TokenTree::Group(group) => {
+                TokenTree::Group(Group::new(group.delimiter(), rename_self(group.stream())))
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Rejects uses of a helper listed in `callees = [...]` by its path, as only
+/// uses by its name are shadowed by the copy made for each specialisation.
+fn check_callee_calls(tokens: TokenStream, callees: &[Callee]) -> Result<(), Error> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Group(group) => check_callee_calls(group.stream(), callees)?,
+            TokenTree::Ident(ident) if callees.iter().any(|callee| callee.name == *ident) => {
+                let after_path = index > 0
+                    && matches!(&tokens[index - 1], TokenTree::Punct(punct) if punct.as_char() == ':');
+                // A module with the same name as the helper is followed by
+                // another path segment.
+                let is_module = matches!(
+                    tokens.get(index + 1..index + 4),
+                    Some([TokenTree::Punct(a), TokenTree::Punct(b), TokenTree::Ident(_)])
+                        if a.as_char() == ':' && b.as_char() == ':'
+                );
+
+                if after_path && !is_module {
+                    return Err(Error::new_at_span(
+                        ident.span(),
+                        format!(
+                            "{ident} is listed in callees, so it must be called by its name rather than by its path"
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn contains_self_ty(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => ident == "Self",
+        TokenTree::Group(group) => contains_self_ty(group.stream()),
+        _ => false,
+    })
+}
+
+/// Venial cannot parse typed receivers such as `self: Box<Self>`, so they are
+/// renamed to [`typed_self_ident`] before parsing, and renamed back by
+/// [`FnBuilder`].
+pub(crate) fn desugar_typed_self(tokens: TokenStream) -> TokenStream {
+    let mut in_signature = false;
+
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Ident(ident) if ident == "fn" => {
+                in_signature = true;
+                TokenTree::Ident(ident)
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ';' => {
+                in_signature = false;
+                TokenTree::Punct(punct)
+            }
+            TokenTree::Group(group) => {
+                let stream = match group.delimiter() {
+                    Delimiter::Parenthesis if in_signature => desugar_params(group.stream()),
+                    Delimiter::Brace if in_signature => {
+                        in_signature = false;
+                        group.stream()
+                    }
+                    Delimiter::Brace => desugar_typed_self(group.stream()),
+                    _ => group.stream(),
+                };
+
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                TokenTree::Group(new_group)
+            }
+            other => other,
+        })
+        .collect()
+}
+
+fn desugar_params(params: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = params.into_iter().collect();
+    let mut i = 0;
+
+    while matches!(&tokens[i..], [TokenTree::Punct(punct), TokenTree::Group(_), ..] if punct.as_char() == '#')
+    {
+        i += 2;
+    }
+
+    if matches!(tokens.get(i), Some(TokenTree::Ident(ident)) if ident == "mut") {
+        i += 1;
+    }
+
+    let is_typed_self = match &tokens[i..] {
+        [TokenTree::Ident(tk_self), TokenTree::Punct(colon), ..] => {
+            *tk_self == "self" && colon.as_char() == ':' && colon.spacing() == Spacing::Alone
+        }
+        _ => false,
+    };
+
+    if is_typed_self {
+        let mut ident = typed_self_ident();
+        ident.set_span(tokens[i].span());
+        tokens[i] = TokenTree::Ident(ident);
+    }
+
+    tokens.into_iter().collect()
+}
@@ -0,0 +1,83 @@
+use crate::clonable_ident;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::quote;
+use venial::{Error, FnParam, Function};
+
+pub fn clonable(attr: TokenStream, orig_func: Function) -> TokenStream {
+    match expand(attr, &orig_func) {
+        Ok(output) => output,
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+/// Keeps the helper as it is, and defines a macro next to it which defines a
+/// copy of the helper with extra attributes. Specialised functions listing the
+/// helper in `callees = [...]` use this to define a copy with their features
+/// enabled inside of each specialisation, which shadows the original.
+fn expand(attr: TokenStream, orig_func: &Function) -> Result<TokenStream, Error> {
+    if let Some(token) = attr.into_iter().next() {
+        return Err(Error::new_at_span(
+            token.span(),
+            "clonable does not take any arguments",
+        ));
+    }
+
+    if orig_func.body.is_none() {
+        return Err(Error::new_at_span(
+            orig_func.name.span(),
+            "clonable cannot take fn items without a body",
+        ));
+    }
+
+    if let Some((FnParam::Receiver(param), _)) = orig_func.params.first() {
+        return Err(Error::new_at_span(
+            param.tk_self.span(),
+            "clonable can only be applied to free functions",
+        ));
+    }
+
+    // `#[target_feature]` cannot be combined with `#[inline(always)]`, so the
+    // copy only gets the inline attribute it is given. Doc comments are not
+    // needed on a copy which is never visible.
+    let attributes = orig_func.attributes.iter().filter(|attribute| {
+        !matches!(
+            attribute.path.as_slice(),
+            [TokenTree::Ident(ident)] if *ident == "inline" || *ident == "doc"
+        )
+    });
+
+    let Function {
+        qualifiers,
+        name,
+        generic_params,
+        params,
+        return_ty,
+        where_clause,
+        body,
+        ..
+    } = orig_func;
+    let tk_arrow = return_ty.as_ref().map(|_| quote! { -> });
+    let macro_ident = clonable_ident(name);
+
+    // A macro can only be used by path once it has been imported, which also
+    // makes it usable before its definition.
+    let use_vis = orig_func.vis_marker.as_ref().map(|_| quote! { pub(crate) });
+
+    Ok(quote! {
+        #orig_func
+
+        #[doc(hidden)]
+        macro_rules! #macro_ident {
+            ($($attribute:meta),*) => {
+                $(#[$attribute])*
+                #(#attributes)*
+                #qualifiers fn #name #generic_params (#params) #tk_arrow #return_ty #where_clause
+                #body
+            };
+        }
+
+        #[doc(hidden)]
+        #[allow(unused_imports)]
+        #use_vis use #macro_ident;
+    })
+}
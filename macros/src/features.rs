@@ -0,0 +1,393 @@
+//! Tables of the target features that can be detected at run-time on each
+//! architecture, or enabled at compile-time on architectures without run-time
+//! detection. Specialisations may only use features from these tables, so
+//! typos are caught regardless of the target being compiled for. The position
+//! of a feature in its table is used as its index into the shared feature
+//! cache, so every expansion in a binary agrees on where the detection result
+//! of a feature is stored.
+//!
+//! These must never contain more than `maybe_special::__private::MAX_FEATURES`
+//! entries.
+
+pub const AARCH64: &[&str] = &[
+    "asimd",
+    "neon",
+    "pmull",
+    "fp",
+    "fp16",
+    "sve",
+    "crc",
+    "lse",
+    "lse2",
+    "lse128",
+    "rdm",
+    "rcpc",
+    "rcpc2",
+    "rcpc3",
+    "dotprod",
+    "tme",
+    "fhm",
+    "dit",
+    "flagm",
+    "flagm2",
+    "ssbs",
+    "sb",
+    "paca",
+    "pacg",
+    "pauth-lr",
+    "dpb",
+    "dpb2",
+    "sve2",
+    "sve2-aes",
+    "sve2-sm4",
+    "sve2-sha3",
+    "sve2-bitperm",
+    "sve2p1",
+    "sve-b16b16",
+    "frintts",
+    "i8mm",
+    "f32mm",
+    "f64mm",
+    "bf16",
+    "rand",
+    "bti",
+    "mte",
+    "jsconv",
+    "fcma",
+    "aes",
+    "sha2",
+    "sha3",
+    "sm4",
+    "hbc",
+    "mops",
+    "ecv",
+    "cssc",
+    "fpmr",
+    "lut",
+    "faminmax",
+    "fp8",
+    "fp8fma",
+    "fp8dot4",
+    "fp8dot2",
+    "wfxt",
+    "sme",
+    "sme-b16b16",
+    "sme-i16i64",
+    "sme-f64f64",
+    "sme-fa64",
+    "sme2",
+    "sme2p1",
+    "sme-f16f16",
+    "sme-lutv2",
+    "sme-f8f16",
+    "sme-f8f32",
+    "ssve-fp8fma",
+    "ssve-fp8dot4",
+    "ssve-fp8dot2",
+];
+
+pub const LOONGARCH: &[&str] = &[
+    "f",
+    "d",
+    "frecipe",
+    "div32",
+    "lsx",
+    "lasx",
+    "lam-bh",
+    "lamcas",
+    "ld-seq-sa",
+    "scq",
+    "lbt",
+    "lvz",
+    "ual",
+];
+
+pub const RISCV: &[&str] = &[
+    "rv32i",
+    "rv32e",
+    "rv64i",
+    "rv128i",
+    "zicsr",
+    "zicntr",
+    "zihpm",
+    "zifencei",
+    "zihintntl",
+    "zihintpause",
+    "zimop",
+    "zicbom",
+    "zicboz",
+    "zicond",
+    "m",
+    "a",
+    "zalrsc",
+    "zaamo",
+    "zawrs",
+    "zabha",
+    "zacas",
+    "zam",
+    "ztso",
+    "f",
+    "d",
+    "q",
+    "zfh",
+    "zfhmin",
+    "zfa",
+    "zfbfmin",
+    "zfinx",
+    "zdinx",
+    "zhinx",
+    "zhinxmin",
+    "c",
+    "zca",
+    "zcf",
+    "zcd",
+    "zcb",
+    "zcmop",
+    "b",
+    "zba",
+    "zbb",
+    "zbc",
+    "zbs",
+    "zbkb",
+    "zbkc",
+    "zbkx",
+    "zknd",
+    "zkne",
+    "zknh",
+    "zksed",
+    "zksh",
+    "zks",
+    "zkn",
+    "zkr",
+    "zkt",
+    "zk",
+    "v",
+    "zve32x",
+    "zve32f",
+    "zve64x",
+    "zve64f",
+    "zve64d",
+    "zvfh",
+    "zvfhmin",
+    "zvfbfmin",
+    "zvfbfwma",
+    "zvbb",
+    "zvbc",
+    "zvkb",
+    "zvkg",
+    "zvkned",
+    "zvknha",
+    "zvknhb",
+    "zvksed",
+    "zvksh",
+    "zvkn",
+    "zvknc",
+    "zvkng",
+    "zvks",
+    "zvksc",
+    "zvksg",
+    "zvkt",
+    "h",
+    "s",
+    "svnapot",
+    "svpbmt",
+    "svinval",
+    "sstc",
+    "unaligned-scalar-mem",
+    "unaligned-vector-mem",
+];
+
+pub const X86: &[&str] = &[
+    "aes",
+    "pclmulqdq",
+    "rdrand",
+    "rdseed",
+    "tsc",
+    "mmx",
+    "sse",
+    "sse2",
+    "sse3",
+    "ssse3",
+    "sse4.1",
+    "sse4.2",
+    "sse4a",
+    "sha",
+    "avx",
+    "avx2",
+    "sha512",
+    "sm3",
+    "sm4",
+    "avx512f",
+    "avx512cd",
+    "avx512er",
+    "avx512pf",
+    "avx512bw",
+    "avx512dq",
+    "avx512vl",
+    "avx512ifma",
+    "avx512vbmi",
+    "avx512vpopcntdq",
+    "avx512vbmi2",
+    "gfni",
+    "vaes",
+    "vpclmulqdq",
+    "avx512vnni",
+    "avx512bitalg",
+    "avx512bf16",
+    "avx512vp2intersect",
+    "avx512fp16",
+    "avxvnni",
+    "avxifma",
+    "avxneconvert",
+    "avxvnniint8",
+    "avxvnniint16",
+    "amx-tile",
+    "amx-int8",
+    "amx-bf16",
+    "amx-fp16",
+    "amx-complex",
+    "amx-avx512",
+    "amx-fp8",
+    "amx-movrs",
+    "amx-tf32",
+    "amx-transpose",
+    "f16c",
+    "fma",
+    "bmi1",
+    "bmi2",
+    "lzcnt",
+    "tbm",
+    "popcnt",
+    "fxsr",
+    "xsave",
+    "xsaveopt",
+    "xsaves",
+    "xsavec",
+    "cmpxchg16b",
+    "kl",
+    "widekl",
+    "adx",
+    "rtm",
+    "movbe",
+    "movrs",
+    "ermsb",
+    "xop",
+    "apxf",
+    "avx10.1",
+    "avx10.2",
+];
+
+pub const ARM: &[&str] = &["neon", "pmull", "crc", "aes", "sha2", "i8mm", "dotprod"];
+
+pub const MIPS: &[&str] = &["msa"];
+
+pub const POWERPC: &[&str] = &[
+    "altivec",
+    "vsx",
+    "power8",
+    "power8-altivec",
+    "power8-vector",
+    "power8-crypto",
+    "power9",
+    "power9-altivec",
+    "power9-vector",
+];
+
+pub const S390X: &[&str] = &[
+    "bear-enhancement",
+    "concurrent-functions",
+    "deflate-conversion",
+    "enhanced-sort",
+    "guarded-storage",
+    "high-word",
+    "message-security-assist-extension3",
+    "message-security-assist-extension4",
+    "message-security-assist-extension5",
+    "message-security-assist-extension8",
+    "message-security-assist-extension9",
+    "message-security-assist-extension12",
+    "miscellaneous-extensions-2",
+    "miscellaneous-extensions-3",
+    "miscellaneous-extensions-4",
+    "nnp-assist",
+    "transactional-execution",
+    "vector",
+    "vector-enhancements-1",
+    "vector-enhancements-2",
+    "vector-enhancements-3",
+    "vector-packed-decimal",
+    "vector-packed-decimal-enhancement",
+    "vector-packed-decimal-enhancement-2",
+    "vector-packed-decimal-enhancement-3",
+];
+
+pub const WASM: &[&str] = &[
+    "simd128",
+    "relaxed-simd",
+    "atomics",
+    "bulk-memory",
+    "exception-handling",
+    "extended-const",
+    "fp16",
+    "multimemory",
+    "multivalue",
+    "mutable-globals",
+    "nontrapping-fptoint",
+    "reference-types",
+    "sign-ext",
+    "tail-call",
+    "wide-arithmetic",
+];
+
+pub const SPARC64: &[&str] = &["leoncasa", "v8plus", "v9"];
+
+pub const CSKY: &[&str] = &[
+    "hard-float",
+    "hard-float-abi",
+    "fpuv2_sf",
+    "fpuv2_df",
+    "fpuv3_hf",
+    "fpuv3_hi",
+    "fpuv3_sf",
+    "fpuv3_df",
+    "fdivdu",
+    "dspv2",
+    "vdspv1",
+    "vdspv2",
+    "edsp",
+    "hwdiv",
+    "high-registers",
+    "e1",
+    "e2",
+    "2e3",
+    "3e3r1",
+    "3e3r2",
+    "3e3r3",
+    "3e7",
+    "7e10",
+    "10e60",
+    "mp",
+    "mp1",
+    "cache",
+    "doloop",
+    "elrw",
+    "smart",
+    "trust",
+    "dsp1e2",
+    "dspe60",
+];
+
+pub const HEXAGON: &[&str] = &["hvx", "hvx-length128b"];
+
+pub const M68K: &[&str] = &[
+    "isa-68000",
+    "isa-68010",
+    "isa-68020",
+    "isa-68030",
+    "isa-68040",
+    "isa-68060",
+    "isa-68881",
+    "isa-68882",
+];
+
+pub const BPF: &[&str] = &["alu32"];
@@ -0,0 +1,272 @@
+use crate::{Architecture, FnBuilder, Specialisation, options::Inputs};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use std::collections::HashMap;
+use venial::{Error, Function};
+
+/// Builds a `#[test]` which checks that every specialisation supported by the
+/// current CPU returns the same result as the generic impl.
+pub(crate) fn build_test(
+    builder: &FnBuilder,
+    orig_func: &Function,
+    specialisations: &HashMap<Architecture, Vec<Specialisation>>,
+    inputs: &Inputs,
+) -> Result<TokenStream, Error> {
+    let name = &orig_func.name;
+
+    // Tests must be free functions, so they cannot be placed inside of `impl`
+    // blocks next to methods or associated functions.
+    if builder.is_method {
+        return Err(Error::new_at_span(
+            name.span(),
+            "test inputs cannot be used on methods or associated functions",
+        ));
+    }
+
+    if orig_func.qualifiers.tk_async.is_some() {
+        return Err(Error::new_at_span(
+            name.span(),
+            "test inputs cannot be used on async functions",
+        ));
+    }
+
+    if builder.is_generic && matches!(inputs, Inputs::Random(_)) {
+        return Err(Error::new_at_span(
+            name.span(),
+            "random test inputs cannot be used on generic functions",
+        ));
+    }
+
+    let generic_ident = builder.generic_ident();
+    let tk_unsafe = &orig_func.qualifiers.tk_unsafe;
+
+    let arch_test = specialisations.iter().map(|(arch, specs)| {
+        let cfg_inner = arch.cfg_inner();
+        let variant = specs.iter().map(Specialisation::variant);
+        let spec_index: Vec<usize> = (1..specs.len() + 1).collect();
+        let spec_ident: Vec<_> = specs.iter().map(|spec| &spec.ident).collect();
+
+        let run = match inputs {
+            Inputs::Supplied(inputs) => {
+                let checks = inputs.iter().map(|input| {
+                    let args = input_args(input);
+                    let spec_call = spec_ident
+                        .iter()
+                        .map(|ident| builder.build_spec_call(ident, &args));
+
+                    quote! {
+                        let expected = #tk_unsafe { #generic_ident(#args) };
+                        #(
+                            if VARIANTS[#spec_index].is_supported() {
+                                ::core::assert_eq!(
+                                    #spec_call,
+                                    expected,
+                                    "{} does not match the generic impl for the input ({})",
+                                    VARIANTS[#spec_index],
+                                    ::core::stringify!(#args),
+                                );
+                            }
+                        )*
+                    }
+                });
+
+                quote! { #({ #checks })* }
+            }
+            Inputs::Random(count) => {
+                let param_tuple = builder.build_param_tuple();
+                let param_pattern = builder.build_param_pattern();
+                let param_idents = &builder.param_idents;
+                let seed = seed(&name.to_string());
+                let spec_call = spec_ident
+                    .iter()
+                    .map(|ident| builder.build_spec_call(ident, param_idents));
+
+                quote! {
+                    let mut rng = ::maybe_special::Rng::new(#seed);
+                    for _ in 0..#count {
+                        let input: #param_tuple = ::maybe_special::Random::random(&mut rng);
+                        let #param_pattern = ::core::clone::Clone::clone(&input);
+                        let expected = #tk_unsafe { #generic_ident(#param_idents) };
+                        #(
+                            if VARIANTS[#spec_index].is_supported() {
+                                let #param_pattern = ::core::clone::Clone::clone(&input);
+                                ::core::assert_eq!(
+                                    #spec_call,
+                                    expected,
+                                    "{} does not match the generic impl for the input {:?}",
+                                    VARIANTS[#spec_index],
+                                    input,
+                                );
+                            }
+                        )*
+                    }
+                }
+            }
+        };
+
+        quote! {
+            #[cfg(#cfg_inner)]
+            {
+                const VARIANTS: &[::maybe_special::Variant] = &[
+                    ::maybe_special::Variant::GENERIC,
+                    #(#variant,)*
+                ];
+
+                #run
+            }
+        }
+    });
+
+    let cfg_attributes = builder.cfg_attributes();
+    let test_ident = format_ident!("{}_matches_generic", name);
+
+    Ok(quote! {
+        #(#cfg_attributes)*
+        #[cfg(test)]
+        #[test]
+        #[allow(unused_unsafe)]
+        fn #test_ident() {
+            #(#arch_test)*
+        }
+    })
+}
+
+/// Builds a function which times the generic impl and every specialisation
+/// supported by the current CPU on the same inputs.
+pub(crate) fn build_bench(
+    builder: &FnBuilder,
+    orig_func: &Function,
+    specialisations: &HashMap<Architecture, Vec<Specialisation>>,
+    inputs: &Inputs,
+) -> Result<TokenStream, Error> {
+    let name = &orig_func.name;
+
+    if !cfg!(feature = "std") {
+        return Err(Error::new_at_span(
+            name.span(),
+            "benchmarks require the std feature",
+        ));
+    }
+
+    if builder.is_method {
+        return Err(Error::new_at_span(
+            name.span(),
+            "bench inputs cannot be used on methods or associated functions",
+        ));
+    }
+
+    if orig_func.qualifiers.tk_async.is_some() {
+        return Err(Error::new_at_span(
+            name.span(),
+            "bench inputs cannot be used on async functions",
+        ));
+    }
+
+    // The inputs are stored before being passed to each specialisation, so
+    // their types must be known.
+    if builder.is_generic {
+        return Err(Error::new_at_span(
+            name.span(),
+            "bench inputs cannot be used on generic functions",
+        ));
+    }
+
+    let generic_ident = builder.generic_ident();
+    let tk_unsafe = &orig_func.qualifiers.tk_unsafe;
+    let param_tuple = builder.build_param_tuple();
+    let param_pattern = builder.build_param_pattern();
+    let param_idents = &builder.param_idents;
+
+    let inputs = match inputs {
+        Inputs::Supplied(inputs) => {
+            let args = inputs.iter().map(input_args);
+            quote! { ::std::vec![#((#args,)),*] }
+        }
+        Inputs::Random(count) => {
+            let seed = seed(&name.to_string());
+            quote! {{
+                let mut rng = ::maybe_special::Rng::new(#seed);
+                (0..#count)
+                    .map(|_| ::maybe_special::Random::random(&mut rng))
+                    .collect()
+            }}
+        }
+    };
+
+    let arch_bench = specialisations.iter().map(|(arch, specs)| {
+        let cfg_inner = arch.cfg_inner();
+        let variant = specs.iter().map(Specialisation::variant);
+        let spec_index = 1..specs.len() + 1;
+        let spec_call = specs
+            .iter()
+            .map(|spec| builder.build_spec_call(&spec.ident, param_idents));
+
+        quote! {
+            #[cfg(#cfg_inner)]
+            {
+                const VARIANTS: &[::maybe_special::Variant] = &[
+                    ::maybe_special::Variant::GENERIC,
+                    #(#variant,)*
+                ];
+
+                #(
+                    if VARIANTS[#spec_index].is_supported() {
+                        timings.push(::maybe_special::__private::bench(
+                            VARIANTS[#spec_index],
+                            &inputs,
+                            iterations,
+                            |#param_pattern: #param_tuple| {
+                                ::core::hint::black_box(#spec_call);
+                            },
+                        ));
+                    }
+                )*
+            }
+        }
+    });
+
+    let cfg_attributes = builder.cfg_attributes();
+    let vis_marker = &orig_func.vis_marker;
+    let bench_ident = format_ident!("{}_bench", name);
+    let bench_doc = format!(
+        "Times [`{}`] and every specialisation of it supported by the current CPU, calling each of them `iterations` times with every bench input.",
+        name
+    );
+
+    Ok(quote! {
+        #(#cfg_attributes)*
+        #[doc = #bench_doc]
+        #[allow(unused_unsafe)]
+        #vis_marker fn #bench_ident(iterations: u32) -> ::std::vec::Vec<::maybe_special::Timing> {
+            let inputs: ::std::vec::Vec<#param_tuple> = #inputs;
+            let mut timings = ::std::vec![::maybe_special::__private::bench(
+                ::maybe_special::Variant::GENERIC,
+                &inputs,
+                iterations,
+                |#param_pattern: #param_tuple| {
+                    ::core::hint::black_box(#tk_unsafe { #generic_ident(#param_idents) });
+                },
+            )];
+
+            #(#arch_bench)*
+            timings
+        }
+    })
+}
+
+/// Returns the arguments of a single supplied input, removing the parentheses
+/// around them if there are any.
+fn input_args(input: &TokenStream) -> TokenStream {
+    match input.clone().into_iter().collect::<Vec<_>>().as_slice() {
+        [TokenTree::Group(group)] if group.delimiter() == Delimiter::Parenthesis => group.stream(),
+        _ => input.clone(),
+    }
+}
+
+/// Derives the seed of the random inputs from the function name, so each test
+/// is deterministic but different functions get different inputs.
+fn seed(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,311 @@
+//! Tables of the features directly implied by each target feature, following
+//! the implications `rustc` itself applies when a feature is enabled. These are
+//! used to find specialisations which can never be selected, and to order
+//! specialisations from best to worst.
+
+use crate::Architecture;
+use std::collections::HashSet;
+
+pub const X86: &[(&str, &[&str])] = &[
+    ("sse2", &["sse"]),
+    ("sse3", &["sse2"]),
+    ("ssse3", &["sse3"]),
+    ("sse4.1", &["ssse3"]),
+    ("sse4.2", &["sse4.1"]),
+    ("sse4a", &["sse3"]),
+    ("avx", &["sse4.2"]),
+    ("avx2", &["avx"]),
+    ("fma", &["avx"]),
+    ("f16c", &["avx"]),
+    ("aes", &["sse2"]),
+    ("pclmulqdq", &["sse2"]),
+    ("sha", &["sse2"]),
+    ("gfni", &["sse2"]),
+    ("vaes", &["avx2", "aes"]),
+    ("vpclmulqdq", &["avx", "pclmulqdq"]),
+    ("sha512", &["avx2"]),
+    ("sm3", &["avx"]),
+    ("sm4", &["avx2"]),
+    ("avx512f", &["avx2", "fma", "f16c"]),
+    ("avx512cd", &["avx512f"]),
+    ("avx512er", &["avx512f"]),
+    ("avx512pf", &["avx512f"]),
+    ("avx512bw", &["avx512f"]),
+    ("avx512dq", &["avx512f"]),
+    ("avx512vl", &["avx512f"]),
+    ("avx512ifma", &["avx512f"]),
+    ("avx512vbmi", &["avx512bw"]),
+    ("avx512vpopcntdq", &["avx512f"]),
+    ("avx512vbmi2", &["avx512bw"]),
+    ("avx512vnni", &["avx512f"]),
+    ("avx512bitalg", &["avx512bw"]),
+    ("avx512bf16", &["avx512bw"]),
+    ("avx512vp2intersect", &["avx512f"]),
+    ("avx512fp16", &["avx512bw"]),
+    ("avxvnni", &["avx2"]),
+    ("avxifma", &["avx2"]),
+    ("avxneconvert", &["avx2"]),
+    ("avxvnniint8", &["avx2"]),
+    ("avxvnniint16", &["avx2"]),
+    ("amx-int8", &["amx-tile"]),
+    ("amx-bf16", &["amx-tile"]),
+    ("amx-fp16", &["amx-tile"]),
+    ("amx-complex", &["amx-tile"]),
+    ("amx-avx512", &["amx-tile"]),
+    ("amx-fp8", &["amx-tile"]),
+    ("amx-movrs", &["amx-tile"]),
+    ("amx-tf32", &["amx-tile"]),
+    ("amx-transpose", &["amx-tile"]),
+    ("xsaveopt", &["xsave"]),
+    ("xsaves", &["xsave"]),
+    ("xsavec", &["xsave"]),
+    ("widekl", &["kl"]),
+    (
+        "avx10.1",
+        &[
+            "avx512bf16",
+            "avx512bitalg",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512f",
+            "avx512fp16",
+            "avx512ifma",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512vl",
+            "avx512vnni",
+            "avx512vpopcntdq",
+        ],
+    ),
+    ("avx10.2", &["avx10.1"]),
+];
+
+pub const AARCH64: &[(&str, &[&str])] = &[
+    ("neon", &["fp"]),
+    ("asimd", &["neon"]),
+    ("fp16", &["neon"]),
+    ("fhm", &["fp16"]),
+    ("rdm", &["neon"]),
+    ("dotprod", &["neon"]),
+    ("fcma", &["neon"]),
+    ("jsconv", &["neon"]),
+    ("i8mm", &["neon"]),
+    ("aes", &["neon"]),
+    ("pmull", &["aes"]),
+    ("sha2", &["neon"]),
+    ("sha3", &["sha2"]),
+    ("sm4", &["neon"]),
+    ("lse128", &["lse"]),
+    ("rcpc2", &["rcpc"]),
+    ("rcpc3", &["rcpc2"]),
+    ("flagm2", &["flagm"]),
+    ("dpb2", &["dpb"]),
+    ("frintts", &["fp"]),
+    ("sve", &["neon"]),
+    ("sve2", &["sve"]),
+    ("sve2-aes", &["sve2", "aes"]),
+    ("sve2-sm4", &["sve2", "sm4"]),
+    ("sve2-sha3", &["sve2", "sha3"]),
+    ("sve2-bitperm", &["sve2"]),
+    ("sve2p1", &["sve2"]),
+    ("sve-b16b16", &["bf16"]),
+    ("f32mm", &["sve"]),
+    ("f64mm", &["sve"]),
+    ("fp8", &["faminmax", "lut", "bf16"]),
+    ("fp8fma", &["fp8"]),
+    ("fp8dot4", &["fp8fma"]),
+    ("fp8dot2", &["fp8dot4"]),
+    ("sme", &["bf16"]),
+    ("sme-b16b16", &["bf16", "sme2", "sve-b16b16"]),
+    ("sme-i16i64", &["sme"]),
+    ("sme-f64f64", &["sme"]),
+    ("sme-fa64", &["sme", "sve2"]),
+    ("sme2", &["sme"]),
+    ("sme2p1", &["sme2"]),
+    ("sme-f16f16", &["sme2"]),
+    ("sme-lutv2", &["sme"]),
+    ("sme-f8f16", &["sme-f8f32"]),
+    ("sme-f8f32", &["sme2", "fp8"]),
+    ("ssve-fp8fma", &["sme2", "fp8"]),
+    ("ssve-fp8dot4", &["ssve-fp8fma"]),
+    ("ssve-fp8dot2", &["ssve-fp8dot4"]),
+];
+
+pub const LOONGARCH: &[(&str, &[&str])] = &[
+    ("d", &["f"]),
+    ("frecipe", &["f"]),
+    ("lsx", &["d"]),
+    ("lasx", &["lsx"]),
+    ("lamcas", &["lam-bh"]),
+];
+
+pub const RISCV: &[(&str, &[&str])] = &[
+    ("a", &["zaamo", "zalrsc"]),
+    ("zawrs", &["zalrsc"]),
+    ("zabha", &["zaamo"]),
+    ("zacas", &["zaamo"]),
+    ("f", &["zicsr"]),
+    ("d", &["f"]),
+    ("q", &["d"]),
+    ("zfhmin", &["f"]),
+    ("zfh", &["zfhmin"]),
+    ("zfa", &["f"]),
+    ("zfbfmin", &["f"]),
+    ("zfinx", &["zicsr"]),
+    ("zdinx", &["zfinx"]),
+    ("zhinxmin", &["zfinx"]),
+    ("zhinx", &["zhinxmin"]),
+    ("c", &["zca"]),
+    ("zcf", &["zca", "f"]),
+    ("zcd", &["zca", "d"]),
+    ("zcb", &["zca"]),
+    ("zcmop", &["zca"]),
+    ("b", &["zba", "zbb", "zbs"]),
+    ("zkn", &["zbkb", "zbkc", "zbkx", "zkne", "zknd", "zknh"]),
+    ("zks", &["zbkb", "zbkc", "zbkx", "zksed", "zksh"]),
+    ("zk", &["zkn", "zkr", "zkt"]),
+    ("zve32x", &["zicsr"]),
+    ("zve32f", &["zve32x", "f"]),
+    ("zve64x", &["zve32x"]),
+    ("zve64f", &["zve32f", "zve64x"]),
+    ("zve64d", &["zve64f", "d"]),
+    ("v", &["zve64d"]),
+    ("zvfhmin", &["zve32f"]),
+    ("zvfh", &["zvfhmin", "zfhmin"]),
+    ("zvfbfmin", &["zve32f"]),
+    ("zvfbfwma", &["zvfbfmin", "zfbfmin"]),
+    ("zvbb", &["zvkb"]),
+    ("zvbc", &["zve64x"]),
+    ("zvkb", &["zve32x"]),
+    ("zvkg", &["zve32x"]),
+    ("zvkned", &["zve32x"]),
+    ("zvknha", &["zve32x"]),
+    ("zvknhb", &["zvknha", "zve64x"]),
+    ("zvksed", &["zve32x"]),
+    ("zvksh", &["zve32x"]),
+    ("zvkn", &["zvkned", "zvknhb", "zvkb", "zvkt"]),
+    ("zvknc", &["zvkn", "zvbc"]),
+    ("zvkng", &["zvkn", "zvkg"]),
+    ("zvks", &["zvksed", "zvksh", "zvkb", "zvkt"]),
+    ("zvksc", &["zvks", "zvbc"]),
+    ("zvksg", &["zvks", "zvkg"]),
+];
+
+pub const ARM: &[(&str, &[&str])] = &[
+    ("aes", &["neon"]),
+    ("sha2", &["neon"]),
+    ("i8mm", &["neon"]),
+    ("dotprod", &["neon"]),
+];
+
+pub const POWERPC: &[(&str, &[&str])] = &[
+    ("vsx", &["altivec"]),
+    ("power8-altivec", &["altivec"]),
+    ("power8-crypto", &["power8-altivec"]),
+    ("power8-vector", &["vsx", "power8-altivec"]),
+    ("power9-altivec", &["power8-altivec"]),
+    ("power9-vector", &["power8-vector", "power9-altivec"]),
+];
+
+pub const S390X: &[(&str, &[&str])] = &[
+    ("vector-enhancements-1", &["vector"]),
+    ("vector-enhancements-2", &["vector-enhancements-1"]),
+    ("vector-enhancements-3", &["vector-enhancements-2"]),
+    ("vector-packed-decimal", &["vector"]),
+    (
+        "vector-packed-decimal-enhancement",
+        &["vector-packed-decimal"],
+    ),
+    (
+        "vector-packed-decimal-enhancement-2",
+        &["vector-packed-decimal-enhancement"],
+    ),
+    (
+        "vector-packed-decimal-enhancement-3",
+        &["vector-packed-decimal-enhancement-2"],
+    ),
+    ("nnp-assist", &["vector"]),
+];
+
+pub const WASM: &[(&str, &[&str])] = &[("relaxed-simd", &["simd128"]), ("fp16", &["simd128"])];
+
+pub const SPARC64: &[(&str, &[&str])] = &[("v8plus", &["v9"])];
+
+pub const HEXAGON: &[(&str, &[&str])] = &[("hvx-length128b", &["hvx"])];
+
+pub const M68K: &[(&str, &[&str])] = &[
+    ("isa-68010", &["isa-68000"]),
+    ("isa-68020", &["isa-68010"]),
+    ("isa-68030", &["isa-68020"]),
+    ("isa-68040", &["isa-68030", "isa-68882"]),
+    ("isa-68060", &["isa-68040"]),
+    ("isa-68882", &["isa-68881"]),
+];
+
+impl Architecture {
+    /// The features directly implied by each feature on this architecture.
+    pub fn implications(&self) -> &'static [(&'static str, &'static [&'static str])] {
+        match self {
+            Self::AARCH64 => AARCH64,
+            Self::LOONGARCH64 | Self::LOONGARCH32 => LOONGARCH,
+            Self::RISCV => RISCV,
+            Self::X86 => X86,
+            Self::ARM => ARM,
+            Self::MIPS64 | Self::MIPS32 => &[],
+            Self::POWERPC64 | Self::POWERPC32 => POWERPC,
+            Self::S390X => S390X,
+            Self::WASM => WASM,
+            Self::SPARC64 => SPARC64,
+            Self::HEXAGON => HEXAGON,
+            Self::M68K => M68K,
+            Self::CSKY | Self::BPF => &[],
+        }
+    }
+
+    /// Returns the given features along with every feature they imply.
+    pub fn implied_features<'a>(
+        &self,
+        features: impl IntoIterator<Item = &'a str>,
+    ) -> HashSet<&'a str> {
+        let mut implied = HashSet::new();
+        let mut stack: Vec<&str> = features.into_iter().collect();
+
+        while let Some(feature) = stack.pop() {
+            if !implied.insert(feature) {
+                continue;
+            }
+
+            if let Some((_, direct)) = self
+                .implications()
+                .iter()
+                .find(|(implier, _)| *implier == feature)
+            {
+                stack.extend(direct.iter().copied());
+            }
+        }
+
+        implied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn implications_are_followed_transitively() {
+        let implied = Architecture::X86.implied_features(["avx512f"]);
+        for feature in ["avx512f", "avx2", "avx", "fma", "f16c", "sse4.2", "sse2"] {
+            assert!(implied.contains(feature), "{feature} is not implied");
+        }
+
+        assert!(!implied.contains("avx512bw"));
+    }
+
+    #[test]
+    fn unrelated_features_are_not_implied() {
+        let implied = Architecture::X86.implied_features(["bmi1", "popcnt"]);
+        assert_eq!(implied, HashSet::from(["bmi1", "popcnt"]));
+    }
+}
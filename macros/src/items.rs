@@ -0,0 +1,302 @@
+use crate::{
+    Scope,
+    r#macro::{Expansion, expand, is_cfg_attribute},
+};
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use venial::{Attribute, Error, Impl, ImplMember, Item, Module};
+
+pub fn make_special_impl(attr: TokenStream, orig_impl: Impl) -> TokenStream {
+    match expand_impl(attr, &orig_impl, false) {
+        Ok((output, _)) => output,
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+pub fn make_special_mod(attr: TokenStream, orig_mod: Module) -> TokenStream {
+    match expand_mod(attr, &orig_mod) {
+        Ok(output) => output,
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+/// Expands every method inside of an `impl` block, returning the new `impl`
+/// block along with the selectors of the methods expanded with `shared`.
+fn expand_impl(
+    attr: TokenStream,
+    orig_impl: &Impl,
+    shared: bool,
+) -> Result<(TokenStream, Vec<TokenStream>), Error> {
+    let in_trait = orig_impl.trait_ty.is_some();
+    let helper_ident = helper_trait_ident(orig_impl);
+    let helper_args = orig_impl
+        .impl_generic_params
+        .as_ref()
+        .map(|generics| generics.as_inline_args());
+    let scope = if in_trait {
+        Scope::TraitImpl(quote! { #helper_ident #helper_args })
+    } else {
+        Scope::Impl
+    };
+    let mut members = Vec::with_capacity(orig_impl.body_items.len());
+    let mut items = vec![];
+    let mut selectors = vec![];
+
+    for member in &orig_impl.body_items {
+        let func = match member {
+            ImplMember::AssocFunction(func) if func.body.is_some() => func,
+            other => {
+                members.push(other.to_token_stream());
+                continue;
+            }
+        };
+
+        let mut func = func.clone();
+        let expansion = match take_item_attr(&mut func.attributes) {
+            ItemAttr::Inherit => expand(attr.clone(), &func, &scope, shared)?,
+            ItemAttr::Override(attr) => expand(attr, &func, &scope, false)?,
+            ItemAttr::Skip => {
+                members.push(func.to_token_stream());
+                continue;
+            }
+        };
+
+        members.push(expansion.outer_def);
+        items.extend(expansion.items);
+        if shared && selectors.is_empty() {
+            selectors = expansion.selectors;
+        }
+    }
+
+    let Impl {
+        attributes,
+        tk_unsafe,
+        tk_impl,
+        impl_generic_params,
+        trait_ty,
+        tk_for,
+        self_ty,
+        where_clause,
+        inner_attributes,
+        ..
+    } = orig_impl;
+
+    let output = if in_trait && !items.is_empty() {
+        // Trait impls cannot contain any extra associated functions, so they
+        // are placed in a hidden helper trait implemented for the same type.
+        // Using the original trait as a supertrait allows the generated
+        // signatures to refer to its associated types through `Self`.
+        let cfg_attributes = attributes
+            .iter()
+            .filter(|attribute| is_cfg_attribute(attribute));
+        let cfg_attributes = quote! { #(#cfg_attributes)* };
+        let declarations = items.iter().map(declaration);
+
+        quote! {
+            #(#attributes)*
+            #tk_unsafe #tk_impl #impl_generic_params #trait_ty #tk_for #self_ty #where_clause {
+                #(#inner_attributes)*
+                #(#members)*
+            }
+
+            #cfg_attributes
+            #[doc(hidden)]
+            #[allow(non_camel_case_types)]
+            trait #helper_ident #impl_generic_params: #trait_ty #where_clause {
+                #(#declarations)*
+            }
+
+            #cfg_attributes
+            impl #impl_generic_params #helper_ident #helper_args for #self_ty #where_clause {
+                #(#items)*
+            }
+        }
+    } else {
+        quote! {
+            #(#attributes)*
+            #tk_unsafe #tk_impl #impl_generic_params #trait_ty #tk_for #self_ty #where_clause {
+                #(#inner_attributes)*
+                #(#members)*
+                #(#items)*
+            }
+        }
+    };
+
+    Ok((output, selectors))
+}
+
+/// Expands every function inside of an inline module. All functions using the
+/// module's specialisations share a single selector for each architecture, so
+/// feature detection is only run once for the entire module.
+fn expand_mod(attr: TokenStream, orig_mod: &Module) -> Result<TokenStream, Error> {
+    if orig_mod.tk_braces.is_none() {
+        return Err(Error::new_at_span(
+            orig_mod.name.span(),
+            "make_special can only accept inline modules",
+        ));
+    }
+
+    let mut members = Vec::with_capacity(orig_mod.members.len());
+    let mut selectors = vec![];
+
+    for member in &orig_mod.members {
+        match member {
+            Item::Function(func) if func.body.is_some() => {
+                let mut func = func.clone();
+                let expansion = match take_item_attr(&mut func.attributes) {
+                    ItemAttr::Inherit => expand(attr.clone(), &func, &Scope::Free, true)?,
+                    ItemAttr::Override(attr) => expand(attr, &func, &Scope::Free, false)?,
+                    ItemAttr::Skip => {
+                        members.push(func.to_token_stream());
+                        continue;
+                    }
+                };
+
+                let Expansion {
+                    outer_def,
+                    items,
+                    selectors: fn_selectors,
+                } = expansion;
+
+                members.push(outer_def);
+                members.extend(items);
+                if selectors.is_empty() {
+                    selectors = fn_selectors;
+                }
+            }
+            Item::Impl(orig_impl) => {
+                let mut orig_impl = orig_impl.clone();
+                match take_item_attr(&mut orig_impl.attributes) {
+                    ItemAttr::Inherit => {
+                        let (output, impl_selectors) = expand_impl(attr.clone(), &orig_impl, true)?;
+                        members.push(output);
+                        if selectors.is_empty() {
+                            selectors = impl_selectors;
+                        }
+                    }
+                    ItemAttr::Override(attr) => {
+                        members.push(expand_impl(attr, &orig_impl, false)?.0);
+                    }
+                    ItemAttr::Skip => members.push(orig_impl.to_token_stream()),
+                }
+            }
+            Item::Module(inner_mod) if inner_mod.tk_braces.is_some() => {
+                let mut inner_mod = inner_mod.clone();
+                members.push(match take_item_attr(&mut inner_mod.attributes) {
+                    ItemAttr::Inherit => expand_mod(attr.clone(), &inner_mod)?,
+                    ItemAttr::Override(attr) => expand_mod(attr, &inner_mod)?,
+                    ItemAttr::Skip => inner_mod.to_token_stream(),
+                });
+            }
+            other => members.push(other.to_token_stream()),
+        }
+    }
+
+    let Module {
+        attributes,
+        vis_marker,
+        tk_unsafe,
+        tk_mod,
+        name,
+        inner_attributes,
+        ..
+    } = orig_mod;
+
+    Ok(quote! {
+        #(#attributes)*
+        #vis_marker #tk_unsafe #tk_mod #name {
+            #(#inner_attributes)*
+            #(#selectors)*
+            #(#members)*
+        }
+    })
+}
+
+enum ItemAttr {
+    Inherit,
+    Override(TokenStream),
+    Skip,
+}
+
+/// Removes a `#[make_special(...)]` attribute from an item inside of an `impl`
+/// block or module that is already being specialised, returning its
+/// arguments.
+fn take_item_attr(attributes: &mut Vec<Attribute>) -> ItemAttr {
+    // Helpers are only specialised as part of the functions calling them.
+    if attributes.iter().any(|attribute| {
+        matches!(attribute.path.last(), Some(TokenTree::Ident(ident)) if *ident == "clonable")
+    }) {
+        return ItemAttr::Skip;
+    }
+
+    let Some(index) = attributes.iter().position(|attribute| {
+        matches!(attribute.path.last(), Some(TokenTree::Ident(ident)) if *ident == "make_special")
+    }) else {
+        return ItemAttr::Inherit;
+    };
+
+    let attr: TokenStream = attributes
+        .remove(index)
+        .get_value_tokens()
+        .iter()
+        .cloned()
+        .collect();
+
+    let mut iter = attr.clone().into_iter();
+    match (iter.next(), iter.next()) {
+        (Some(TokenTree::Ident(ident)), None) if ident == "skip" => ItemAttr::Skip,
+        _ => ItemAttr::Override(attr),
+    }
+}
+
+fn helper_trait_ident(orig_impl: &Impl) -> Ident {
+    let mut hasher = DefaultHasher::new();
+    orig_impl
+        .impl_generic_params
+        .to_token_stream()
+        .to_string()
+        .hash(&mut hasher);
+    orig_impl
+        .trait_ty
+        .to_token_stream()
+        .to_string()
+        .hash(&mut hasher);
+    orig_impl
+        .self_ty
+        .to_token_stream()
+        .to_string()
+        .hash(&mut hasher);
+
+    format_ident!("_MakeSpecial_{:016x}", hasher.finish())
+}
+
+/// Turns a generated function into a trait method declaration, keeping only
+/// its `#[cfg]` attributes.
+fn declaration(item: &TokenStream) -> TokenStream {
+    let mut tokens = item.clone().into_iter().peekable();
+    let mut output = TokenStream::new();
+
+    while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+        if punct.as_char() != '#' {
+            break;
+        }
+
+        let tk_hash = tokens.next();
+        let Some(TokenTree::Group(group)) = tokens.next() else {
+            unreachable!("generated attributes are always followed by a group")
+        };
+
+        if matches!(group.stream().into_iter().next(), Some(TokenTree::Ident(ident)) if ident == "cfg")
+        {
+            output.extend(tk_hash);
+            output.extend([TokenTree::Group(group)]);
+        }
+    }
+
+    let mut signature: Vec<TokenTree> = tokens.collect();
+    signature.pop();
+    output.extend(signature);
+    output.extend(quote! { ; });
+    output
+}
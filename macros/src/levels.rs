@@ -0,0 +1,229 @@
+//! Named microarchitecture levels and profiles, which expand into the full
+//! list of target features they require. Only features which can be detected
+//! at run-time are listed, so a level may not include every feature that its
+//! specification mandates.
+
+use crate::Architecture;
+
+pub struct Level {
+    pub name: &'static str,
+    /// The level which this one extends, if any.
+    pub base: Option<&'static str>,
+    /// The features added on top of the base level.
+    pub features: &'static [&'static str],
+}
+
+/// The x86-64 psABI microarchitecture levels.
+pub const X86: &[Level] = &[
+    Level {
+        name: "x86-64-v2",
+        base: None,
+        features: &["cmpxchg16b", "popcnt", "sse3", "ssse3", "sse4.1", "sse4.2"],
+    },
+    Level {
+        name: "x86-64-v3",
+        base: Some("x86-64-v2"),
+        features: &[
+            "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+        ],
+    },
+    Level {
+        name: "x86-64-v4",
+        base: Some("x86-64-v3"),
+        features: &["avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl"],
+    },
+];
+
+/// The Arm architecture versions, including the features which became
+/// mandatory in each of them.
+pub const AARCH64: &[Level] = &[
+    Level {
+        name: "armv8-a",
+        base: None,
+        features: &["neon"],
+    },
+    Level {
+        name: "armv8.1-a",
+        base: Some("armv8-a"),
+        features: &["crc", "lse", "rdm"],
+    },
+    Level {
+        name: "armv8.2-a",
+        base: Some("armv8.1-a"),
+        features: &["dpb"],
+    },
+    Level {
+        name: "armv8.3-a",
+        base: Some("armv8.2-a"),
+        features: &["rcpc", "paca", "pacg", "fcma", "jsconv"],
+    },
+    Level {
+        name: "armv8.4-a",
+        base: Some("armv8.3-a"),
+        features: &["dotprod", "dit", "flagm", "lse2", "rcpc2"],
+    },
+    Level {
+        name: "armv8.5-a",
+        base: Some("armv8.4-a"),
+        features: &["bti", "dpb2", "flagm2", "frintts", "sb", "ssbs"],
+    },
+    Level {
+        name: "armv8.6-a",
+        base: Some("armv8.5-a"),
+        features: &["bf16", "i8mm"],
+    },
+    Level {
+        name: "armv9-a",
+        base: Some("armv8.5-a"),
+        features: &["sve", "sve2"],
+    },
+    Level {
+        name: "armv9.1-a",
+        base: Some("armv9-a"),
+        features: &["bf16", "i8mm"],
+    },
+];
+
+/// The RISC-V application profiles.
+pub const RISCV: &[Level] = &[
+    Level {
+        name: "rva22u64",
+        base: None,
+        features: &[
+            "m",
+            "a",
+            "f",
+            "d",
+            "c",
+            "zicsr",
+            "zicntr",
+            "zihpm",
+            "zihintpause",
+            "zicbom",
+            "zicboz",
+            "zfhmin",
+            "zba",
+            "zbb",
+            "zbs",
+            "zkt",
+        ],
+    },
+    Level {
+        name: "rva23u64",
+        base: Some("rva22u64"),
+        features: &[
+            "v",
+            "zvfhmin",
+            "zvbb",
+            "zvkt",
+            "zihintntl",
+            "zicond",
+            "zimop",
+            "zcmop",
+            "zcb",
+            "zfa",
+            "zawrs",
+        ],
+    },
+];
+
+impl Architecture {
+    /// The named levels which can be used in place of a feature list on this
+    /// architecture.
+    pub fn levels(&self) -> &'static [Level] {
+        match self {
+            Self::X86 => X86,
+            Self::AARCH64 => AARCH64,
+            Self::RISCV => RISCV,
+            _ => &[],
+        }
+    }
+
+    /// Returns every feature required by a level, starting with those of its
+    /// base level.
+    pub fn level_features(&self, name: &str) -> Option<Vec<&'static str>> {
+        let level = self
+            .levels()
+            .iter()
+            .find(|level| level.name.eq_ignore_ascii_case(name))?;
+
+        let mut features = match level.base {
+            Some(base) => self.level_features(base)?,
+            None => vec![],
+        };
+        features.extend(level.features);
+
+        Some(features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(arch: Architecture, name: &str) -> Vec<&'static str> {
+        arch.level_features(name).unwrap()
+    }
+
+    #[test]
+    fn levels_extend_their_base() {
+        for arch in [
+            Architecture::X86,
+            Architecture::AARCH64,
+            Architecture::RISCV,
+        ] {
+            for level in arch.levels() {
+                let Some(base) = level.base else {
+                    continue;
+                };
+
+                let base_features = arch
+                    .level_features(base)
+                    .unwrap_or_else(|| panic!("{} extends unknown level {base}", level.name));
+                let level_features = features(arch, level.name);
+                for feature in base_features {
+                    assert!(
+                        level_features.contains(&feature),
+                        "{} does not include {feature} from {base}",
+                        level.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn level_names_ignore_case() {
+        assert_eq!(
+            features(Architecture::X86, "X86-64-V2"),
+            features(Architecture::X86, "x86-64-v2")
+        );
+    }
+
+    #[test]
+    fn unknown_levels() {
+        assert_eq!(Architecture::X86.level_features("x86-64-v5"), None);
+        assert_eq!(Architecture::X86.level_features("armv8-a"), None);
+        assert_eq!(Architecture::ARM.level_features("armv8-a"), None);
+    }
+
+    #[test]
+    fn level_features_can_be_detected() {
+        for arch in [
+            Architecture::X86,
+            Architecture::AARCH64,
+            Architecture::RISCV,
+        ] {
+            for level in arch.levels() {
+                for feature in features(arch, level.name) {
+                    assert!(
+                        arch.feature_index(feature).is_some(),
+                        "{feature} in {} is not a known {} feature",
+                        level.name,
+                        arch.as_str()
+                    );
+                }
+            }
+        }
+    }
+}
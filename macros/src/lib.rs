@@ -0,0 +1,70 @@
+//! Implementation of the [`maybe_special`](https://docs.rs/maybe_special)
+//! attribute macros. This crate should not be used directly, as the code it
+//! generates refers to the runtime in `maybe_special`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
+use venial::{Error, Item};
+
+mod adapter;
+mod arch;
+mod builder;
+mod clonable;
+mod features;
+mod harness;
+mod implications;
+mod items;
+mod levels;
+mod r#macro;
+mod options;
+mod spec;
+
+pub(crate) use arch::Architecture;
+pub(crate) use builder::{FnBuilder, Scope};
+pub(crate) use spec::Specialisation;
+
+pub(crate) fn generic_ident() -> Ident {
+    Ident::new("_generic", Span::call_site())
+}
+
+pub(crate) fn typed_self_ident() -> Ident {
+    Ident::new("__maybe_special_self", Span::call_site())
+}
+
+/// The macro defined by `#[clonable]` next to a helper, which defines a copy
+/// of that helper.
+pub(crate) fn clonable_ident(name: &Ident) -> Ident {
+    Ident::new(&format!("__maybe_special_clonable_{}", name), name.span())
+}
+
+/// Refer to the [crate-level documentation](https://docs.rs/maybe_special)
+#[proc_macro_attribute]
+pub fn make_special(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match venial::parse_item(builder::desugar_typed_self(item.into())) {
+        Ok(Item::Function(func)) => r#macro::make_special(attr.into(), func),
+        Ok(Item::Impl(orig_impl)) => items::make_special_impl(attr.into(), orig_impl),
+        Ok(Item::Module(orig_mod)) => items::make_special_mod(attr.into(), orig_mod),
+        Ok(item) => Error::new_at_span(
+            item.span(),
+            "make_special can only accept fn, impl or mod items",
+        )
+        .to_compile_error(),
+        Err(err) => err.to_compile_error(),
+    }
+    .into()
+}
+
+/// Refer to the [crate-level documentation](https://docs.rs/maybe_special)
+#[proc_macro_attribute]
+pub fn clonable(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match venial::parse_item(item.into()) {
+        Ok(Item::Function(func)) => clonable::clonable(attr.into(), func),
+        Ok(item) => {
+            Error::new_at_span(item.span(), "clonable can only accept fn items").to_compile_error()
+        }
+        Err(err) => err.to_compile_error(),
+    }
+    .into()
+}
@@ -0,0 +1,724 @@
+use crate::{
+    FnBuilder, Scope, Specialisation,
+    adapter::Adapter,
+    harness,
+    options::{Init, Options, Order},
+};
+use indexmap::IndexSet;
+use proc_macro2::{Ident, Literal, Span, TokenStream, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use venial::{Attribute, Error, Function};
+
+pub fn make_special(attr: TokenStream, orig_func: Function) -> TokenStream {
+    match expand(attr, &orig_func, &Scope::Free, false) {
+        Ok(Expansion {
+            outer_def, items, ..
+        }) => quote! {
+            #outer_def
+            #(#items)*
+        },
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+pub(crate) struct Expansion {
+    pub outer_def: TokenStream,
+    /// Items which must be placed next to the outer function.
+    pub items: Vec<TokenStream>,
+    /// Functions which select a specialisation index for each architecture,
+    /// these are only used if the function was expanded with `shared`.
+    pub selectors: Vec<TokenStream>,
+}
+
+pub(crate) fn is_cfg_attribute(attribute: &Attribute) -> bool {
+    matches!(attribute.path.as_slice(), [TokenTree::Ident(ident)] if *ident == "cfg")
+}
+
+/// Expands a single function. When `shared` is set, the function selects its
+/// specialisation using the selector functions in the surrounding module
+/// rather than running feature detection itself.
+pub(crate) fn expand(
+    attr: TokenStream,
+    orig_func: &Function,
+    scope: &Scope,
+    shared: bool,
+) -> Result<Expansion, Error> {
+    let (options, attr) = Options::parse(attr)?;
+    let builder = FnBuilder::new(orig_func, scope, &options)?;
+    let mut specialisations = Specialisation::parse(&builder, &options, attr)?;
+    for specs in specialisations.values_mut() {
+        if options.order == Order::Auto {
+            Specialisation::sort_best_first(specs);
+        }
+
+        Specialisation::sort_by_priority(specs);
+    }
+
+    let warnings: Vec<TokenStream> = specialisations
+        .values()
+        .map(|specs| Specialisation::shadowed_warnings(specs))
+        .collect();
+
+    let generic_ident = builder.generic_ident();
+    let generic_call = builder.build_call(&generic_ident);
+    let closure_params = builder.closure_param_idents();
+    let param_idents = &builder.param_idents;
+    let mut items = builder.build_generic();
+    items.extend(
+        specialisations
+            .values()
+            .flatten()
+            .map(ToTokens::to_token_stream),
+    );
+    let mut arch_call = Vec::with_capacity(specialisations.len());
+    let mut selectors = Vec::with_capacity(specialisations.len());
+    let mut arch_variants = Vec::with_capacity(specialisations.len());
+    let mut arch_selected = Vec::with_capacity(specialisations.len());
+    let mut arch_index = Vec::with_capacity(specialisations.len());
+    let mut arch_resolve = Vec::with_capacity(specialisations.len());
+    let name = &orig_func.name;
+
+    for (arch, specs) in &specialisations {
+        let cfg_inner = arch.cfg_inner();
+        let dispatch_ident = builder.inner_ident(arch.dispatch_ident());
+        let jump_ref_ident = arch.jump_ref_ident();
+        let init_ident = arch.init_ident();
+        let select_ident = arch.select_ident();
+
+        let features: IndexSet<String> = specs
+            .iter()
+            .flat_map(|spec| spec.features.clone())
+            .collect();
+
+        let feature_literal: Vec<Literal> = features
+            .iter()
+            .map(|feature| Literal::string(feature))
+            .collect();
+
+        // ADAPTER
+
+        // Every specialisation of a function returning a future, iterator or
+        // closure returns its own type of it, so they are combined into a
+        // single value which is created for the selected specialisation.
+        // Architectures without run-time detection only create values for the
+        // generic impl and manual impls.
+        let adapter_specs: Vec<(usize, &Specialisation)> = (2..)
+            .zip(specs)
+            .filter(|(_, spec)| !arch.is_static_only() || spec.is_manual)
+            .collect();
+        let adapter_def = builder
+            .adapter
+            .as_ref()
+            .map(|adapter| adapter.build_def(&adapter_specs));
+        let adapter_value = |index: usize, value: TokenStream| match builder.adapter {
+            Some(Adapter::Opaque) | None => value,
+            Some(_) => Adapter::wrap_value(index, value),
+        };
+        let spec_value = |ident: &Ident| {
+            let index = (2..)
+                .zip(specs)
+                .find(|(_, spec)| spec.ident == *ident)
+                .map_or(1, |(index, _)| index);
+            adapter_value(index, builder.build_spec_call(ident, param_idents))
+        };
+
+        // A closure has to be wrapped in a single closure, so the selected
+        // value is bound to a variable rather than being returned.
+        let closure = match &builder.adapter {
+            Some(Adapter::Closure { kind, args }) => Some((kind, args)),
+            _ => None,
+        };
+        let exit = if closure.is_some() {
+            quote! { break 'value }
+        } else {
+            quote! { return }
+        };
+
+        // INIT
+
+        let dispatch_call = builder.build_call(&dispatch_ident);
+
+        // Architectures without run-time detection can only use static
+        // dispatch, so they fall back to the generic impl when no
+        // specialisation is enabled.
+        let (accessor_index, resolve_select, jump_ref, init, dyn_call) = if arch.is_static_only() {
+            (
+                quote! { 1 },
+                None,
+                quote! {},
+                quote! {},
+                adapter_value(1, generic_call.clone()),
+            )
+        } else {
+            let spec_criteria = specs.iter().map(|spec| {
+                let feature_pat = features.iter().map(|feature| {
+                    if spec.features.contains(feature) {
+                        quote! { true }
+                    } else {
+                        quote! { _ }
+                    }
+                });
+
+                quote! {
+                    (#(#feature_pat),*)
+                }
+            });
+
+            let spec_index: Vec<usize> = (2..specs.len() + 2).collect();
+
+            // With the `override` feature, features can also be disabled for the
+            // current thread.
+            let feature_detected = features.iter().map(|feature| {
+                let detect = arch.detect_feature(feature, options.detect.as_ref());
+                if cfg!(feature = "override") {
+                    quote! { (::maybe_special::__private::allowed(#feature) && #detect) }
+                } else {
+                    detect
+                }
+            });
+
+            let detected = quote! {
+                match (#(#feature_detected),*) {
+                    #(#spec_criteria => #spec_index,)*
+                    _ => 1
+                }
+            };
+
+            // The selector caches the index of the selected specialisation, where
+            // 1 is the generic impl and each specialisation starts from 2.
+            let selector_ident = if shared {
+                select_ident.clone()
+            } else {
+                builder.inner_ident(select_ident.clone())
+            };
+
+            // Overrides can change at any time, so the selected specialisation
+            // cannot be cached when they are enabled.
+            let select_body = if cfg!(feature = "override") {
+                detected.clone()
+            } else {
+                quote! {
+                    static SELECTED: ::core::sync::atomic::AtomicUsize =
+                        ::core::sync::atomic::AtomicUsize::new(0);
+
+                    match SELECTED.load(::core::sync::atomic::Ordering::Relaxed) {
+                        0 => {
+                            let index = #detected;
+                            SELECTED.store(index, ::core::sync::atomic::Ordering::Relaxed);
+                            index
+                        }
+                        index => index,
+                    }
+                }
+            };
+
+            let selector = quote! {
+                #[cfg(#cfg_inner)]
+                #[doc(hidden)]
+                #[inline]
+                fn #selector_ident() -> usize {
+                    #select_body
+                }
+            };
+
+            // The selector is placed next to the function, so the dispatcher and
+            // the accessors all share its cached index.
+            let select_call = if shared {
+                selectors.push(selector);
+                quote! { #selector_ident() }
+            } else {
+                items.push(selector);
+                let selector_path = builder.build_path(&selector_ident);
+                quote! { #selector_path() }
+            };
+
+            // The constructor cannot refer to `Self`, so the selector of a
+            // method is only warmed up by detecting its features.
+            let eager_select = if builder.is_method && !shared {
+                detected
+            } else {
+                select_call.clone()
+            };
+
+            let (jump_ref, init) = if builder.use_jump_table {
+                // The jump table dispatches on the cached index directly.
+                let eager = if options.init == Init::Eager {
+                    build_eager(quote! { let _ = #eager_select; })
+                } else {
+                    quote! {}
+                };
+
+                (eager, quote! {})
+            } else {
+                let spec_ident = specs.iter().map(|spec| &spec.ident);
+                let fn_ptr = builder.build_ptr();
+                let store = quote! {
+                    unsafe {
+                        #jump_ref_ident.set(match #select_call {
+                            #(#spec_index => #spec_ident as *mut (),)*
+                            _ => #generic_ident as *mut ()
+                        });
+                    }
+                };
+
+                let eager = if options.init == Init::Eager {
+                    build_eager(store.clone())
+                } else {
+                    quote! {}
+                };
+
+                (
+                    quote! {
+                        static #jump_ref_ident: ::maybe_special::__private::DispatchSlot<#fn_ptr> =
+                            unsafe {
+                                ::maybe_special::__private::DispatchSlot::new(
+                                    #init_ident as *mut ()
+                                )
+                            };
+                        #eager
+                    },
+                    builder.build_detail(
+                        &[],
+                        false, //copy_const
+                        true,  //copy_unsafe
+                        &init_ident,
+                        quote! {
+                            #store
+                            #dispatch_call
+                        },
+                    ),
+                )
+            };
+
+            let dyn_call = if builder.use_jump_table {
+                let generic_value = adapter_value(1, generic_call.clone());
+                let spec_call = specs.iter().map(|spec| spec_value(&spec.ident));
+                quote! {
+                    match #select_call {
+                        1 => #generic_value,
+                        #(
+                            #spec_index => #spec_call,
+                        )*
+                        _ => unsafe { ::core::hint::unreachable_unchecked() }
+                    }
+                }
+            } else {
+                let tk_unsafe = &orig_func.qualifiers.tk_unsafe;
+                quote! {
+                    #tk_unsafe { #jump_ref_ident.get()(#param_idents) }
+                }
+            };
+
+            (
+                select_call.clone(),
+                Some(select_call),
+                jump_ref,
+                init,
+                dyn_call,
+            )
+        };
+
+        // STATIC
+
+        // When the features of a specialisation are enabled at compile-time,
+        // the generic impl is already compiled with them. Architectures
+        // without run-time detection have no other way of reaching a manual
+        // impl, so only they call it instead.
+        let calls_manual = arch.is_static_only() && specs.iter().any(|spec| spec.is_manual);
+        let mut static_targets = vec![];
+        if !calls_manual {
+            static_targets.push((feature_literal.clone(), None));
+        }
+        static_targets.extend(specs.iter().filter(|spec| spec.is_static).map(|spec| {
+            let feature = spec.features.iter().map(|feature| Literal::string(feature));
+            let manual = calls_manual && spec.is_manual;
+            (feature.collect(), manual.then_some(&spec.ident))
+        }));
+
+        // SELECTED
+
+        let variant = specs.iter().map(Specialisation::variant);
+        // The accessor reports the impl that static dispatch calls, which is
+        // the generic impl unless a manual impl is called directly.
+        let static_selected = static_returns(&static_targets, &quote! { return }, |ident| {
+            let index = specs
+                .iter()
+                .position(|spec| Some(&spec.ident) == ident)
+                .map_or(0, |index| index + 1);
+            quote! { VARIANTS[#index] }
+        });
+
+        let variants = quote! {
+            const VARIANTS: &[::maybe_special::Variant] = &[
+                ::maybe_special::Variant::GENERIC,
+                #(#variant,)*
+            ];
+        };
+
+        arch_variants.push(quote! {
+            #[cfg(#cfg_inner)]
+            {
+                #variants
+                return VARIANTS;
+            }
+        });
+
+        arch_selected.push(quote! {
+            #[cfg(#cfg_inner)]
+            #[allow(unreachable_code)]
+            {
+                #variants
+
+                #static_selected
+                return VARIANTS[#accessor_index - 1];
+            }
+        });
+
+        // DISPATCH
+
+        let static_call = static_returns(&static_targets, &exit, |ident| match ident {
+            Some(ident) => spec_value(ident),
+            None => adapter_value(1, generic_call.clone()),
+        });
+
+        let select = match closure {
+            Some((kind, args)) => Adapter::build_closure(
+                kind,
+                args,
+                &adapter_specs,
+                quote! {
+                    {
+                        #static_call
+                        #dyn_call
+                    }
+                },
+            ),
+            None => quote! {
+                #static_call
+                #dyn_call
+            },
+        };
+
+        items.push(builder.build_detail(
+            &[
+                quote!(cfg(#cfg_inner)),
+                quote!(allow(unreachable_code, unused_unsafe)),
+                quote!(inline(always)),
+            ],
+            false, //copy_const
+            false, //copy_unsafe
+            &dispatch_ident,
+            quote! {
+                #adapter_def
+                #jump_ref
+                #init
+
+                #select
+            },
+        ));
+
+        // RESOLVE
+
+        let spec_index = 2..specs.len() + 2;
+        let spec_ident = specs.iter().map(|spec| &spec.ident);
+        arch_resolve.push(if builder.use_jump_table {
+            let closure_call = |ident: Option<&Ident>| {
+                let path = builder.build_path(ident.unwrap_or(&generic_ident));
+                match ident {
+                    Some(_) => quote! { unsafe { #path(#closure_params) } },
+                    None => quote! { #path(#closure_params) },
+                }
+            };
+
+            let static_call = static_returns(&static_targets, &quote! { return }, closure_call);
+            let dyn_call = resolve_select.as_ref().map(|select| {
+                arch_index.push(quote! {
+                    #[cfg(#cfg_inner)]
+                    break 'index #select;
+                });
+
+                let generic_call = closure_call(None);
+                let spec_call = specs.iter().map(|spec| closure_call(Some(&spec.ident)));
+                quote! {
+                    return match index {
+                        1 => #generic_call,
+                        #(#spec_index => #spec_call,)*
+                        _ => unsafe { ::core::hint::unreachable_unchecked() }
+                    };
+                }
+            });
+
+            quote! {
+                #[cfg(#cfg_inner)]
+                {
+                    #static_call
+                    #dyn_call
+                }
+            }
+        } else {
+            let cast = |ident: Option<&Ident>| {
+                let ident = ident.unwrap_or(&generic_ident);
+                quote! { unsafe { ::maybe_special::__private::cast(#ident as *mut ()) } }
+            };
+
+            let static_ptr = static_returns(&static_targets, &quote! { return }, cast);
+            let dyn_ptr = match &resolve_select {
+                Some(select) => quote! {
+                    unsafe {
+                        ::maybe_special::__private::cast(match #select {
+                            #(#spec_index => #spec_ident as *mut (),)*
+                            _ => #generic_ident as *mut ()
+                        })
+                    }
+                },
+                None => cast(None),
+            };
+
+            quote! {
+                #[cfg(#cfg_inner)]
+                {
+                    #static_ptr
+                    return #dyn_ptr;
+                }
+            }
+        });
+
+        // ARCH CALL
+
+        arch_call.push(if orig_func.qualifiers.tk_const.is_some() {
+            let safe_generic_ident =
+                builder.inner_ident(Ident::new("_safe_generic", Span::call_site()));
+            let safe_generic_path = builder.build_path(&safe_generic_ident);
+            let dispatch_path = builder.build_path(&dispatch_ident);
+
+            items.push(builder.build_detail(
+                &[quote!(cfg(#cfg_inner)), quote!(inline(always))],
+                true,  //copy_const
+                false, //copy_unsafe
+                &safe_generic_ident,
+                generic_call.clone(),
+            ));
+
+            quote! {
+                #[cfg(#cfg_inner)]
+                return ::core::intrinsics::const_eval_select((#param_idents), #safe_generic_path, #dispatch_path);
+            }
+        } else {
+            quote! {
+                #[cfg(#cfg_inner)]
+                return #dispatch_call;
+            }
+        });
+    }
+
+    // The dispatcher of a function with an adapter returns a different type to
+    // the generic impl, so the generic impl is only returned when there is no
+    // dispatcher.
+    let fallback = if builder.adapter.is_some() {
+        let arch_cfg = specialisations.keys().map(|arch| arch.cfg_inner());
+        quote! {
+            #[cfg(not(any(#(#arch_cfg),*)))]
+            return #generic_call;
+        }
+    } else {
+        quote! {
+            #[allow(unreachable_code)]
+            #generic_call
+        }
+    };
+
+    let attributes = &orig_func.attributes;
+    let vis_marker = &orig_func.vis_marker;
+    let cfg_attributes = builder.cfg_attributes();
+    let cfg_attributes = quote! { #(#cfg_attributes)* };
+
+    // Trait items cannot have a visibility.
+    let accessor_vis = if builder.in_trait() {
+        None
+    } else {
+        vis_marker.as_ref()
+    };
+    let path_prefix = if builder.is_method { "Self::" } else { "" };
+    let variants_ident = format_ident!("{}_variants", name);
+    let variants_doc = format!(
+        "Returns every specialisation of [`{}{}`] for the current architecture, starting with the generic impl.",
+        path_prefix, name
+    );
+    let selected_ident = format_ident!("{}_selected", name);
+    let selected_doc = format!(
+        "Returns the specialisation of [`{}{}`] selected for the current CPU.",
+        path_prefix, name
+    );
+    let accessors = options.accessors.then(|| {
+        quote! {
+            #cfg_attributes
+            #[doc = #variants_doc]
+            #accessor_vis fn #variants_ident() -> &'static [::maybe_special::Variant] {
+                #(#arch_variants)*
+                #[allow(unreachable_code)]
+                &[::maybe_special::Variant::GENERIC]
+            }
+
+            #cfg_attributes
+            #[doc = #selected_doc]
+            #accessor_vis fn #selected_ident() -> ::maybe_special::Variant {
+                #(#arch_selected)*
+                #[allow(unreachable_code)]
+                ::maybe_special::Variant::GENERIC
+            }
+        }
+    });
+
+    let resolve_body = if builder.use_jump_table {
+        let generic_path = builder.build_path(&generic_ident);
+        quote! {
+            let index: usize = 'index: {
+                #(#arch_index)*
+                #[allow(unreachable_code)]
+                1
+            };
+
+            move |#closure_params| {
+                #(#arch_resolve)*
+                #[allow(unreachable_code)]
+                #generic_path(#closure_params)
+            }
+        }
+    } else {
+        quote! {
+            #(#arch_resolve)*
+            #[allow(unreachable_code)]
+            unsafe { ::maybe_special::__private::cast(#generic_ident as *mut ()) }
+        }
+    };
+
+    let resolve_ident = format_ident!("{}_resolve", name);
+    let resolve_doc = format!(
+        "Returns the specialisation of [`{}{}`] selected for the current CPU, which can be called repeatedly without dispatching again.",
+        path_prefix, name
+    );
+    let resolve = options
+        .accessors
+        .then(|| builder.build_resolve(&resolve_ident, resolve_body))
+        .flatten()
+        .map(|resolve| {
+            quote! {
+                #cfg_attributes
+                #[doc = #resolve_doc]
+                #[allow(unreachable_code, unused_unsafe, unused_variables)]
+                #[inline]
+                #accessor_vis #resolve
+            }
+        });
+
+    let test = match &options.test {
+        Some(inputs) => Some(harness::build_test(
+            &builder,
+            orig_func,
+            &specialisations,
+            inputs,
+        )?),
+        None => None,
+    };
+
+    let bench = match &options.bench {
+        Some(inputs) => Some(harness::build_bench(
+            &builder,
+            orig_func,
+            &specialisations,
+            inputs,
+        )?),
+        None => None,
+    };
+
+    // Items inside of a function body cannot be reached by the accessors or
+    // the harness, and items inside of a method body cannot refer to `Self`,
+    // so they are placed next to the function in those cases. Otherwise they
+    // stay inside of its body, as a function which does not refer to `Self`
+    // may still be an associated function which cannot call them without it.
+    let in_body = !builder.is_method && accessors.is_none() && test.is_none() && bench.is_none();
+    let inner_items = if in_body {
+        std::mem::take(&mut items)
+    } else {
+        vec![]
+    };
+
+    let outer_def = builder.build_detail(
+        &[],  //attributes
+        true, //copy_const
+        true, //copy_unsafe
+        name,
+        quote! {
+            #(#inner_items)*
+            #(#warnings)*
+            #(#arch_call)*
+            #fallback
+        },
+    );
+
+    let items = items
+        .into_iter()
+        .map(|item| {
+            quote! {
+                #cfg_attributes
+                #[doc(hidden)]
+                #item
+            }
+        })
+        .chain(accessors)
+        .chain(resolve)
+        .chain(test)
+        .chain(bench)
+        .collect();
+
+    Ok(Expansion {
+        outer_def: quote! { #(#attributes)* #vis_marker #outer_def },
+        items,
+        selectors,
+    })
+}
+
+/// Builds a constructor which runs `body` when the program starts, before any
+/// specialised function can be called. This is only supported on ELF targets,
+/// elsewhere the specialisation is still selected on the first call.
+fn build_eager(body: TokenStream) -> TokenStream {
+    quote! {
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "dragonfly",
+            target_os = "illumos"
+        ))]
+        #[used]
+        #[unsafe(link_section = ".init_array")]
+        static EAGER_INIT: extern "C" fn() = {
+            extern "C" fn eager_init() {
+                #body
+            }
+            eager_init
+        };
+    }
+}
+
+/// Builds an early exit for each set of features which selects an impl at
+/// compile-time, where `exit` is either `return` or a labelled `break`, and
+/// `value` builds the value from the manual impl that is selected, or `None`
+/// for the generic impl.
+fn static_returns(
+    targets: &[(Vec<Literal>, Option<&Ident>)],
+    exit: &TokenStream,
+    value: impl Fn(Option<&Ident>) -> TokenStream,
+) -> TokenStream {
+    let returns = targets.iter().map(|(features, ident)| {
+        let value = value(*ident);
+        quote! {
+            #[cfg(all(#(target_feature = #features),*))]
+            #exit #value;
+        }
+    });
+
+    quote! { #(#returns)* }
+}
@@ -0,0 +1,270 @@
+use crate::clonable_ident;
+use proc_macro2::{Delimiter, Ident, TokenStream, TokenTree};
+use quote::quote;
+use venial::Error;
+
+/// Options which can be given to the macro alongside the specialisations, in
+/// the form `name = value`.
+#[derive(Default)]
+pub(crate) struct Options {
+    pub test: Option<Inputs>,
+    pub bench: Option<Inputs>,
+    pub order: Order,
+    pub detect: Option<Detect>,
+    pub init: Init,
+    /// The name the feature token is bound to inside of the function body, in
+    /// the form `token = name`.
+    pub token: Option<Ident>,
+    /// The helpers in the form `callees = [helper, path::to::helper]`, which
+    /// every specialisation defines its own copy of.
+    pub callees: Vec<Callee>,
+    /// Whether the `_selected`, `_variants` and `_resolve` accessors are
+    /// generated, in the form `accessors = true`.
+    pub accessors: bool,
+}
+
+/// A helper listed in `callees = [...]`.
+pub(crate) struct Callee {
+    pub name: Ident,
+    /// The macro defined by `#[clonable]` next to the helper, which defines a
+    /// copy of it.
+    pub clonable: TokenStream,
+}
+
+/// A custom feature detection backend, in the form `detect = path::to::detect`
+/// for a `fn(&str) -> bool`, or `detect = path::to::detect!` for a macro.
+pub(crate) struct Detect {
+    pub path: TokenStream,
+    pub is_macro: bool,
+}
+
+impl Detect {
+    /// Builds a call to the backend which detects the given feature.
+    pub fn call(&self, feature: &str) -> TokenStream {
+        let path = &self.path;
+        if self.is_macro {
+            quote! { #path!(#feature) }
+        } else {
+            quote! { #path(#feature) }
+        }
+    }
+}
+
+/// The order in which specialisations are checked when selecting one.
+#[derive(Default, PartialEq, Eq)]
+pub(crate) enum Order {
+    /// The order the specialisations were declared in, in the form
+    /// `order = declared`.
+    #[default]
+    Declared,
+    /// Specialisations are sorted so that each one is checked before those
+    /// whose features it implies, in the form `order = auto`.
+    Auto,
+}
+
+/// When the specialisation is selected.
+#[derive(Default, PartialEq, Eq)]
+pub(crate) enum Init {
+    /// On the first call, in the form `init = lazy`.
+    #[default]
+    Lazy,
+    /// When the program starts, using a constructor in `.init_array` on ELF
+    /// targets, in the form `init = eager`. Other targets fall back to lazy
+    /// initialisation.
+    Eager,
+}
+
+/// The inputs which generated tests and benchmarks call each specialisation
+/// with.
+pub(crate) enum Inputs {
+    /// A list of argument tuples, in the form `[(a, b), (c, d)]`.
+    Supplied(Vec<TokenStream>),
+    /// A number of randomly generated inputs, in the form `random(n)`.
+    Random(usize),
+}
+
+impl Options {
+    /// Splits any options out of the attribute, returning them along with the
+    /// remaining specialisations.
+    pub fn parse(attr: TokenStream) -> Result<(Self, TokenStream), Error> {
+        let mut options = Self::default();
+        let mut specs = TokenStream::new();
+
+        for entry in split_entries(attr) {
+            let (name, value) = match entry.as_slice() {
+                [TokenTree::Ident(name), TokenTree::Punct(punct), value @ ..]
+                    if punct.as_char() == '=' && is_option(&name.to_string()) =>
+                {
+                    (name, value)
+                }
+                _ => {
+                    specs.extend(entry);
+                    specs.extend(quote! { , });
+                    continue;
+                }
+            };
+
+            match name.to_string().as_str() {
+                "test" => options.test = Some(parse_inputs(name, value)?),
+                "bench" => options.bench = Some(parse_inputs(name, value)?),
+                "order" => options.order = parse_order(name, value)?,
+                "detect" => options.detect = Some(parse_detect(name, value)?),
+                "init" => options.init = parse_init(name, value)?,
+                "token" => options.token = Some(parse_token(name, value)?),
+                "callees" => options.callees = parse_callees(name, value)?,
+                "accessors" => options.accessors = parse_bool(name, value)?,
+                _ => unreachable!(),
+            }
+        }
+
+        Ok((options, specs))
+    }
+}
+
+fn is_option(name: &str) -> bool {
+    matches!(
+        name,
+        "test" | "bench" | "order" | "detect" | "init" | "token" | "callees" | "accessors"
+    )
+}
+
+/// Splits the attribute at each top-level comma.
+fn split_entries(attr: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut entries = vec![];
+    let mut entry = vec![];
+
+    for token in attr {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                entries.push(std::mem::take(&mut entry));
+            }
+            other => entry.push(other),
+        }
+    }
+
+    if !entry.is_empty() {
+        entries.push(entry);
+    }
+
+    entries
+}
+
+fn parse_inputs(name: &Ident, value: &[TokenTree]) -> Result<Inputs, Error> {
+    match value {
+        [TokenTree::Group(group)] if group.delimiter() == Delimiter::Bracket => {
+            Ok(Inputs::Supplied(
+                split_entries(group.stream())
+                    .into_iter()
+                    .map(|input| input.into_iter().collect())
+                    .collect(),
+            ))
+        }
+        [TokenTree::Ident(ident), TokenTree::Group(group)]
+            if ident == "random" && group.delimiter() == Delimiter::Parenthesis =>
+        {
+            let count = group.stream().to_string().parse().map_err(|_| {
+                Error::new_at_span(
+                    group.span(),
+                    format!("expected a number of inputs but got {}", group.stream()),
+                )
+            })?;
+
+            Ok(Inputs::Random(count))
+        }
+        _ => Err(Error::new_at_span(
+            name.span(),
+            format!("expected {name} = [(inputs), ...] or {name} = random(count)"),
+        )),
+    }
+}
+
+fn parse_order(name: &Ident, value: &[TokenTree]) -> Result<Order, Error> {
+    match value {
+        [TokenTree::Ident(ident)] if ident == "declared" => Ok(Order::Declared),
+        [TokenTree::Ident(ident)] if ident == "auto" => Ok(Order::Auto),
+        _ => Err(Error::new_at_span(
+            name.span(),
+            "expected order = declared or order = auto",
+        )),
+    }
+}
+
+fn parse_init(name: &Ident, value: &[TokenTree]) -> Result<Init, Error> {
+    match value {
+        [TokenTree::Ident(ident)] if ident == "lazy" => Ok(Init::Lazy),
+        [TokenTree::Ident(ident)] if ident == "eager" => Ok(Init::Eager),
+        _ => Err(Error::new_at_span(
+            name.span(),
+            "expected init = lazy or init = eager",
+        )),
+    }
+}
+
+fn parse_bool(name: &Ident, value: &[TokenTree]) -> Result<bool, Error> {
+    match value {
+        [TokenTree::Ident(ident)] if ident == "true" => Ok(true),
+        [TokenTree::Ident(ident)] if ident == "false" => Ok(false),
+        _ => Err(Error::new_at_span(
+            name.span(),
+            format!("expected {name} = true or {name} = false"),
+        )),
+    }
+}
+
+fn parse_token(name: &Ident, value: &[TokenTree]) -> Result<Ident, Error> {
+    match value {
+        [TokenTree::Ident(ident)] => Ok(ident.clone()),
+        _ => Err(Error::new_at_span(name.span(), "expected token = name")),
+    }
+}
+
+fn parse_callees(name: &Ident, value: &[TokenTree]) -> Result<Vec<Callee>, Error> {
+    let error = || Error::new_at_span(name.span(), "expected callees = [helper, ...]");
+    let group = match value {
+        [TokenTree::Group(group)] if group.delimiter() == Delimiter::Bracket => group,
+        _ => return Err(error()),
+    };
+
+    split_entries(group.stream())
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.as_slice() {
+            [path @ .., TokenTree::Ident(helper)] if is_path(path) => {
+                let macro_ident = clonable_ident(helper);
+                Ok(Callee {
+                    name: helper.clone(),
+                    clonable: quote! { #(#path)* #macro_ident },
+                })
+            }
+            _ => Err(error()),
+        })
+        .collect()
+}
+
+fn parse_detect(name: &Ident, value: &[TokenTree]) -> Result<Detect, Error> {
+    let (path, is_macro) = match value {
+        [path @ .., TokenTree::Punct(punct)] if punct.as_char() == '!' => (path, true),
+        path => (path, false),
+    };
+
+    if path.is_empty() || !is_path(path) {
+        return Err(Error::new_at_span(
+            name.span(),
+            "expected detect = path::to::detect or detect = path::to::detect!",
+        ));
+    }
+
+    Ok(Detect {
+        path: path.iter().cloned().collect(),
+        is_macro,
+    })
+}
+
+/// Whether the tokens only consist of identifiers separated by `::`.
+fn is_path(tokens: &[TokenTree]) -> bool {
+    tokens.iter().all(|token| match token {
+        TokenTree::Ident(_) => true,
+        TokenTree::Punct(punct) => punct.as_char() == ':',
+        _ => false,
+    })
+}
@@ -0,0 +1,770 @@
+use crate::{
+    Architecture, FnBuilder,
+    adapter::Adapter,
+    options::{Detect, Options},
+};
+use indexmap::IndexSet;
+use proc_macro2::{Ident, Literal, Span, TokenStream, TokenTree};
+use quote::{ToTokens, quote};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+use venial::Error;
+
+macro_rules! expect_token {
+    ($token:ident = $expr:expr, $msg:literal) => {
+        match $expr {
+            Some(TokenTree::$token(token)) => token,
+            Some(other) => {
+                return Err(Error::new_at_span(
+                    other.span(),
+                    format!("expected {} but got {}", $msg, other),
+                ))
+            }
+            None => return Err(Error::new(format!("expected {} but found nothing", $msg))),
+        }
+    };
+}
+
+pub struct Specialisation<'a> {
+    builder: &'a FnBuilder<'a>,
+    detect: Option<&'a Detect>,
+    pub arch: Architecture,
+    pub features: IndexSet<String>,
+    pub is_static: bool,
+    pub is_manual: bool,
+    pub ident: Ident,
+    /// Specialisations with a higher priority are checked first.
+    pub priority: i32,
+    pub span: Span,
+}
+
+impl<'a> Specialisation<'a> {
+    pub(crate) fn parse(
+        builder: &'a FnBuilder<'a>,
+        options: &'a Options,
+        attr: TokenStream,
+    ) -> Result<HashMap<Architecture, Vec<Self>>, Error> {
+        let mut output = HashMap::new();
+        let mut iter = attr.into_iter();
+
+        while let Some(TokenTree::Ident(arch_ident)) = iter.next() {
+            let is_static;
+            let arch: Architecture = match arch_ident.to_string() {
+                val if val == "static" => {
+                    is_static = true;
+                    expect_token!(Ident = iter.next(), "an architecture").to_string()
+                }
+                other => {
+                    is_static = false;
+                    other
+                }
+            }
+            .parse()
+            .map_err(|_| {
+                Error::new_at_span(
+                    arch_ident.span(),
+                    format!("{} is not a supported architecture", arch_ident),
+                )
+            })?;
+
+            if arch.is_static_only() && !is_static {
+                return Err(Error::new_at_span(
+                    arch_ident.span(),
+                    format!(
+                        "{} has no run-time feature detection, so its specialisations must be static",
+                        arch.as_str()
+                    ),
+                ));
+            }
+
+            let arch_str = arch.as_str();
+            let mut name = String::with_capacity(1 + arch_str.len());
+            name.push('_');
+            name.push_str(arch_str);
+
+            let _equals = iter
+                .next()
+                .ok_or_else(|| Error::new("expected = but found nothing"))?;
+
+            let features = parse_features(&mut iter, arch, &mut name)?;
+            if arch.uses_auxv(options.detect.as_ref()) {
+                let undetectable = arch.auxv_undetectable();
+                if let Some(feature) = features
+                    .iter()
+                    .find(|feature| undetectable.contains(&feature.as_str()))
+                {
+                    return Err(Error::new_at_span(
+                        arch_ident.span(),
+                        format!(
+                            "{feature} cannot be detected through the auxiliary vector, so it cannot be used with the auxv feature"
+                        ),
+                    ));
+                }
+            }
+
+            let (ident, priority) = parse_suffix(&mut iter)?;
+            let is_manual;
+            let ident = match ident {
+                Some(ident) => {
+                    // A manual impl returns its own opaque type, which cannot
+                    // be returned in place of the one from the generic impl.
+                    if matches!(builder.adapter, Some(Adapter::Opaque)) {
+                        return Err(Error::new_at_span(
+                            ident.span(),
+                            "manual impls cannot be used on functions returning this impl Trait",
+                        ));
+                    }
+
+                    is_manual = true;
+                    ident
+                }
+                None => {
+                    is_manual = false;
+                    builder.inner_ident(Ident::new(&name, Span::call_site()))
+                }
+            };
+
+            // Identical specialisations would define the same item twice.
+            let specs: &mut Vec<Self> = output.entry(arch).or_default();
+            if let Some(earlier) = specs.iter().find(|spec| spec.features == features) {
+                return Err(Error::new_at_span(
+                    arch_ident.span(),
+                    format!("{} is specialised more than once", earlier.name()),
+                ));
+            }
+
+            specs.push(Specialisation {
+                builder,
+                detect: options.detect.as_ref(),
+                arch,
+                features,
+                is_static,
+                is_manual,
+                ident,
+                priority,
+                span: arch_ident.span(),
+            });
+        }
+
+        Ok(output)
+    }
+}
+
+fn parse_features(
+    iter: &mut impl Iterator<Item = TokenTree>,
+    arch: Architecture,
+    name: &mut String,
+) -> Result<IndexSet<String>, Error> {
+    let mut features = IndexSet::new();
+    let group = match iter.next() {
+        Some(TokenTree::Ident(ident)) if ident == "level" => {
+            let group = expect_token!(Group = iter.next(), "(\"level\")");
+            for feature in parse_level(&ident, group.stream(), arch)? {
+                push_feature(&mut features, name, feature.to_owned());
+            }
+
+            return Ok(features);
+        }
+        other => expect_token!(Group = other, "[\"feature\", \"feature\", ...]"),
+    };
+
+    let mut iter = group.stream().into_iter();
+
+    loop {
+        match iter.next() {
+            Some(TokenTree::Literal(lit)) => {
+                if let litrs::Literal::String(inner) = lit.clone().into() {
+                    let feature = inner.into_value();
+                    check_feature(&lit, &feature, arch)?;
+                    push_feature(&mut features, name, feature);
+                } else {
+                    return Err(Error::new_at_span(
+                        lit.span(),
+                        format!("expected a string literal but got {}", lit),
+                    ));
+                }
+            }
+            Some(TokenTree::Ident(ident)) if ident == "level" => {
+                let group = expect_token!(Group = iter.next(), "(\"level\")");
+                for feature in parse_level(&ident, group.stream(), arch)? {
+                    push_feature(&mut features, name, feature.to_owned());
+                }
+            }
+            _ => break,
+        }
+
+        match iter.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+            Some(other) => {
+                return Err(Error::new_at_span(
+                    other.span(),
+                    format!("expected , but got {}", other),
+                ));
+            }
+            None => break,
+        }
+    }
+
+    if features.is_empty() {
+        Err(Error::new("expected features but found nothing"))
+    } else {
+        Ok(features)
+    }
+}
+
+/// Checks that a feature is known for the architecture, so that typos are
+/// caught on every host rather than only when compiling for that architecture.
+fn check_feature(lit: &Literal, feature: &str, arch: Architecture) -> Result<(), Error> {
+    if arch.feature_index(feature).is_some() {
+        return Ok(());
+    }
+
+    let suggestion = arch
+        .known_features()
+        .iter()
+        .map(|known| (edit_distance(feature, known), known))
+        .filter(|(distance, _)| *distance <= (feature.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance);
+
+    let message = match suggestion {
+        Some((_, known)) => format!(
+            "{} is not a known {} feature, did you mean {}?",
+            feature,
+            arch.as_str(),
+            known
+        ),
+        None => format!("{} is not a known {} feature", feature, arch.as_str()),
+    };
+
+    Err(Error::new_at_span(lit.span(), message))
+}
+
+/// The number of single character insertions, deletions or substitutions
+/// needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_ch) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b_ch) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_ch != *b_ch);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Adds a feature to the set, and to the name of the specialisation if it
+/// hasn't already been added.
+fn push_feature(features: &mut IndexSet<String>, name: &mut String, feature: String) {
+    if features.contains(&feature) {
+        return;
+    }
+
+    name.reserve(feature.len() + 1);
+    name.push('_');
+    for ch in feature.chars() {
+        if unicode_ident::is_xid_continue(ch) {
+            name.push(ch);
+        }
+    }
+
+    features.insert(feature);
+}
+
+/// Parses the inside of `level("name")` into the features of that level.
+fn parse_level(
+    ident: &Ident,
+    stream: TokenStream,
+    arch: Architecture,
+) -> Result<Vec<&'static str>, Error> {
+    let mut iter = stream.into_iter();
+    let lit = expect_token!(Literal = iter.next(), "a level name");
+    let level = match lit.clone().into() {
+        litrs::Literal::String(inner) => inner.into_value(),
+        _ => {
+            return Err(Error::new_at_span(
+                lit.span(),
+                format!("expected a string literal but got {}", lit),
+            ));
+        }
+    };
+
+    if let Some(other) = iter.next() {
+        return Err(Error::new_at_span(
+            other.span(),
+            format!("expected ) but got {}", other),
+        ));
+    }
+
+    arch.level_features(&level).ok_or_else(|| {
+        let known: Vec<&str> = arch.levels().iter().map(|level| level.name).collect();
+        let message = if known.is_empty() {
+            format!("{} has no known levels", arch.as_str())
+        } else {
+            format!(
+                "{} is not a known level for {}, expected one of {}",
+                level,
+                arch.as_str(),
+                known.join(", ")
+            )
+        };
+
+        Error::new_at_span(ident.span(), message)
+    })
+}
+
+/// Parses everything after the features of a specialisation up to the next
+/// comma, which is an optional manual impl and an optional priority.
+fn parse_suffix(iter: &mut impl Iterator<Item = TokenTree>) -> Result<(Option<Ident>, i32), Error> {
+    let mut ident = None;
+    let mut priority = 0;
+
+    loop {
+        match iter.next() {
+            None => break,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => break,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {
+                let _gt = iter.next();
+                ident = Some(parse_ident(iter)?);
+            }
+            Some(TokenTree::Ident(keyword)) if keyword == "priority" => {
+                priority = parse_priority(iter)?;
+            }
+            Some(other) => {
+                return Err(Error::new_at_span(
+                    other.span(),
+                    format!(
+                        "expected => unsafe some_impl, priority = N or , but got {}",
+                        other
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok((ident, priority))
+}
+
+fn parse_ident(iter: &mut impl Iterator<Item = TokenTree>) -> Result<Ident, Error> {
+    let unsafe_ident = expect_token!(Ident = iter.next(), "unsafe");
+    let unsafe_str = unsafe_ident.to_string();
+    if unsafe_str == "unsafe" {
+        Ok(expect_token!(Ident = iter.next(), "ident"))
+    } else {
+        Err(Error::new_at_span(
+            unsafe_ident.span(),
+            "manual impls must be prefixed with unsafe",
+        ))
+    }
+}
+
+fn parse_priority(iter: &mut impl Iterator<Item = TokenTree>) -> Result<i32, Error> {
+    let equals = expect_token!(Punct = iter.next(), "=");
+    if equals.as_char() != '=' {
+        return Err(Error::new_at_span(
+            equals.span(),
+            format!("expected = but got {}", equals),
+        ));
+    }
+
+    let mut priority = String::new();
+    let lit = match iter.next() {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '-' => {
+            priority.push('-');
+            expect_token!(Literal = iter.next(), "a priority")
+        }
+        other => expect_token!(Literal = other, "a priority"),
+    };
+
+    priority.push_str(&lit.to_string());
+    priority.parse().map_err(|_| {
+        Error::new_at_span(
+            lit.span(),
+            format!("expected a priority but got {}", priority),
+        )
+    })
+}
+
+impl Specialisation<'_> {
+    /// The name of this specialisation, in the form `arch+feature1,feature2`.
+    pub fn name(&self) -> String {
+        let features: Vec<&str> = self.features.iter().map(String::as_str).collect();
+        format!("{}+{}", self.arch.as_str(), features.join(","))
+    }
+
+    /// Returns the features of this specialisation along with every feature
+    /// they imply.
+    pub fn implied_features(&self) -> HashSet<&str> {
+        self.arch
+            .implied_features(self.features.iter().map(String::as_str))
+    }
+
+    /// Sorts specialisations so that each one comes before every
+    /// specialisation whose features it implies, otherwise keeping the order
+    /// they were declared in.
+    pub fn sort_best_first(specs: &mut Vec<Self>) {
+        let mut remaining = std::mem::take(specs);
+
+        while !remaining.is_empty() {
+            let implied: Vec<_> = remaining.iter().map(Self::implied_features).collect();
+            let best = (0..remaining.len())
+                .find(|&i| {
+                    !implied
+                        .iter()
+                        .any(|other| implied[i].len() < other.len() && implied[i].is_subset(other))
+                })
+                .unwrap_or(0);
+
+            specs.push(remaining.remove(best));
+        }
+    }
+
+    /// Sorts specialisations from the highest priority to the lowest, otherwise
+    /// keeping their current order.
+    pub fn sort_by_priority(specs: &mut [Self]) {
+        specs.sort_by_key(|spec| Reverse(spec.priority));
+    }
+
+    /// Builds a warning for every specialisation which can never be selected,
+    /// as an earlier specialisation requires a subset of its features.
+    pub fn shadowed_warnings(specs: &[Self]) -> TokenStream {
+        let implied: Vec<_> = specs.iter().map(Self::implied_features).collect();
+
+        let warnings = specs.iter().enumerate().filter_map(|(i, spec)| {
+            let (shadow, shadow_implied) = specs[..i]
+                .iter()
+                .zip(&implied)
+                .find(|(_, earlier)| earlier.is_subset(&implied[i]))?;
+
+            let message = if shadow_implied.len() == implied[i].len() {
+                format!(
+                    "{} is never selected, as it requires the same features as {}",
+                    spec.name(),
+                    shadow.name()
+                )
+            } else {
+                let hint = if shadow.priority == spec.priority {
+                    "consider `order = auto`"
+                } else {
+                    "consider changing its priority"
+                };
+
+                format!(
+                    "{} is never selected, as {} is checked before it and requires a subset of its features ({})",
+                    spec.name(),
+                    shadow.name(),
+                    hint
+                )
+            };
+
+            // There are no warnings for proc macros on stable, so this uses
+            // a deprecated item instead, pointing at the specialisation.
+            let ident = Ident::new("never_selected", spec.span);
+            Some(quote! {
+                {
+                    #[deprecated(note = #message)]
+                    #[allow(non_camel_case_types)]
+                    struct never_selected;
+                    let _ = #ident;
+                }
+            })
+        });
+
+        quote! { #(#warnings)* }
+    }
+
+    /// Builds the `Variant` describing this specialisation at run-time, which
+    /// is named in the form `arch+feature1,feature2`.
+    pub fn variant(&self) -> TokenStream {
+        let arch = self.arch;
+        let features: Vec<&str> = self.features.iter().map(String::as_str).collect();
+        let name = self.name();
+        let supported = if arch.is_static_only() {
+            quote! { ::core::cfg!(all(#(target_feature = #features),*)) }
+        } else {
+            let detect = features
+                .iter()
+                .map(|feature| arch.detect_feature(feature, self.detect));
+            quote! { true #(&& #detect)* }
+        };
+
+        quote! {
+            ::maybe_special::Variant::new(#name, #arch, &[#(#features),*], || {
+                #supported
+            })
+        }
+    }
+}
+
+impl Specialisation<'_> {
+    /// The features of this specialisation, in the form taken by
+    /// `#[target_feature(enable = "...")]`.
+    pub fn enabled_features(&self) -> Literal {
+        let mut features = String::new();
+
+        for feature in &self.features {
+            if !features.is_empty() {
+                features.push(',');
+            }
+
+            features.reserve(feature.len() + 1);
+            features.push_str(feature);
+        }
+
+        Literal::string(&features)
+    }
+}
+
+impl ToTokens for Specialisation<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        if self.is_manual {
+            return;
+        }
+
+        let enabled_features = self.enabled_features();
+        let cfg_inner = self.arch.cfg_inner();
+        let attributes = &[
+            quote!(cfg(#cfg_inner)),
+            quote!(target_feature(enable = #enabled_features)),
+            quote!(inline),
+        ];
+
+        if matches!(self.builder.adapter, Some(Adapter::Opaque)) {
+            tokens.extend(self.builder.build_trampoline(attributes, &self.ident));
+            return;
+        }
+
+        // The token is defined inside of the clone, so it can only be created
+        // once the clone has been selected.
+        let token_def = self.builder.uses_token().then(|| {
+            let variant = self.variant();
+            quote! {
+                #[derive(Clone, Copy)]
+                struct SpecToken;
+
+                unsafe impl ::maybe_special::Token for SpecToken {
+                    const VARIANT: ::maybe_special::Variant = #variant;
+
+                    #[inline(always)]
+                    fn vectorize<R>(self, f: impl FnOnce() -> R) -> R {
+                        #[target_feature(enable = #enabled_features)]
+                        #[inline]
+                        unsafe fn vectorize<R>(f: impl FnOnce() -> R) -> R {
+                            f()
+                        }
+
+                        unsafe { vectorize(f) }
+                    }
+                }
+            }
+        });
+
+        tokens.extend(if !self.builder.callees.is_empty() {
+            // The body cannot call a copy of the helpers if it is shared with
+            // the generic impl, so each clone gets its own copy of it too.
+            let callees = &self.builder.callees;
+            let bind_token = self.builder.bind_token(quote! { SpecToken });
+            self.builder.build_inline_clone(
+                attributes,
+                &self.ident,
+                quote! {
+                    #token_def
+                    #(#callees!(target_feature(enable = #enabled_features), inline, allow(dead_code));)*
+                    #bind_token
+                },
+            )
+        } else {
+            let body = match token_def {
+                Some(token_def) => {
+                    let token_call = self.builder.build_token_call(quote! { SpecToken });
+                    quote! {
+                        #token_def
+                        #token_call
+                    }
+                }
+                None => self.builder.build_call(&self.builder.generic_ident()),
+            };
+
+            self.builder.build_clone(attributes, &self.ident, body)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scope;
+    use venial::Item;
+
+    /// Parses the specialisations of an empty function, passing the x86 ones
+    /// to `check`.
+    fn with_x86_specs<R>(
+        attr: TokenStream,
+        check: impl FnOnce(Result<Vec<Specialisation>, Error>) -> R,
+    ) -> R {
+        let func = match venial::parse_item(quote! { fn f() {} }) {
+            Ok(Item::Function(func)) => func,
+            _ => unreachable!(),
+        };
+        let options = Options::default();
+        let builder = FnBuilder::new(&func, &Scope::Free, &options).unwrap();
+        let specs = Specialisation::parse(&builder, &options, attr)
+            .map(|mut specs| specs.remove(&Architecture::X86).unwrap_or_default());
+
+        check(specs)
+    }
+
+    fn parse_error(attr: TokenStream) -> String {
+        with_x86_specs(attr, |specs| match specs {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string(),
+        })
+    }
+
+    fn names(specs: &[Specialisation]) -> Vec<String> {
+        specs.iter().map(Specialisation::name).collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("avx2", "avx2"), 0);
+        assert_eq!(edit_distance("avx", "avx2"), 1);
+        assert_eq!(edit_distance("avx3", "avx2"), 1);
+        assert_eq!(edit_distance("sse41", "sse4.1"), 1);
+        assert_eq!(edit_distance("", "fma"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn known_feature_is_accepted() {
+        with_x86_specs(quote! { x86 = ["avx2", "sse4.1"] }, |specs| {
+            assert_eq!(names(&specs.unwrap()), ["x86+avx2,sse4.1"]);
+        });
+    }
+
+    #[test]
+    fn unknown_feature_suggests_closest_feature() {
+        assert_eq!(
+            parse_error(quote! { x86 = ["avx512fl"] }),
+            "avx512fl is not a known x86 feature, did you mean avx512f?"
+        );
+        assert_eq!(
+            parse_error(quote! { x86 = ["sse41"] }),
+            "sse41 is not a known x86 feature, did you mean sse4.1?"
+        );
+    }
+
+    #[test]
+    fn suggestion_is_limited_to_a_third_of_the_length() {
+        // Up to two edits are allowed for a feature of six or more characters.
+        assert_eq!(
+            parse_error(quote! { x86 = ["avx5xxf"] }),
+            "avx5xxf is not a known x86 feature, did you mean avx512f?"
+        );
+        assert_eq!(
+            parse_error(quote! { x86 = ["avx5xxxf"] }),
+            "avx5xxxf is not a known x86 feature"
+        );
+        // But only one for shorter features.
+        assert_eq!(
+            parse_error(quote! { x86 = ["fmaa"] }),
+            "fmaa is not a known x86 feature, did you mean fma?"
+        );
+        assert_eq!(
+            parse_error(quote! { x86 = ["fmxx"] }),
+            "fmxx is not a known x86 feature"
+        );
+    }
+
+    #[test]
+    fn unrelated_feature_has_no_suggestion() {
+        assert_eq!(
+            parse_error(quote! { x86 = ["neon"] }),
+            "neon is not a known x86 feature"
+        );
+    }
+
+    #[test]
+    fn sort_best_first_checks_implying_features_first() {
+        let attr = quote! { x86 = ["bmi1"], x86 = ["sse4.1"], x86 = ["avx2"], x86 = ["avx512f"] };
+        with_x86_specs(attr, |specs| {
+            let mut specs = specs.unwrap();
+            Specialisation::sort_best_first(&mut specs);
+            assert_eq!(
+                names(&specs),
+                ["x86+bmi1", "x86+avx512f", "x86+avx2", "x86+sse4.1"]
+            );
+        });
+    }
+
+    #[test]
+    fn shadowed_specialisation_is_warned_about() {
+        with_x86_specs(quote! { x86 = ["avx2"], x86 = ["avx512f"] }, |specs| {
+            let mut specs = specs.unwrap();
+            let warnings = Specialisation::shadowed_warnings(&specs).to_string();
+            assert!(warnings.contains(
+                "x86+avx512f is never selected, as x86+avx2 is checked before it and requires a subset of its features (consider `order = auto`)"
+            ));
+
+            Specialisation::sort_best_first(&mut specs);
+            assert!(Specialisation::shadowed_warnings(&specs).is_empty());
+        });
+    }
+
+    #[test]
+    fn higher_priority_is_checked_first() {
+        let attr = quote! {
+            x86 = ["avx512f", "avx512bw"],
+            x86 = ["avx2"],
+            x86 = ["vaes"] priority = 1,
+            x86 = ["sse4.1"] priority = -1,
+        };
+        with_x86_specs(attr, |specs| {
+            let mut specs = specs.unwrap();
+            Specialisation::sort_best_first(&mut specs);
+            Specialisation::sort_by_priority(&mut specs);
+            assert_eq!(
+                names(&specs),
+                ["x86+vaes", "x86+avx512f,avx512bw", "x86+avx2", "x86+sse4.1"]
+            );
+        });
+    }
+
+    #[test]
+    fn shadowed_warning_suggests_priority_when_priorities_differ() {
+        with_x86_specs(
+            quote! { x86 = ["avx2"] priority = 1, x86 = ["avx512f"] },
+            |specs| {
+                let warnings = Specialisation::shadowed_warnings(&specs.unwrap()).to_string();
+                assert!(warnings.contains("(consider changing its priority)"));
+            },
+        );
+    }
+
+    #[test]
+    fn equivalent_specialisation_is_warned_about() {
+        // `avx2` implies `avx`, so both require the same features.
+        with_x86_specs(quote! { x86 = ["avx2"], x86 = ["avx2", "avx"] }, |specs| {
+            let warnings = Specialisation::shadowed_warnings(&specs.unwrap()).to_string();
+            assert!(warnings.contains(
+                "x86+avx2,avx is never selected, as it requires the same features as x86+avx2"
+            ));
+        });
+    }
+
+    #[test]
+    fn duplicate_specialisation_is_rejected() {
+        assert_eq!(
+            parse_error(quote! { x86 = ["avx2", "fma"], static x86 = ["fma", "avx2"] }),
+            "x86+avx2,fma is specialised more than once"
+        );
+    }
+}
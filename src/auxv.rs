@@ -0,0 +1,314 @@
+//! Run-time feature detection on AArch64 and RISC-V Linux using the
+//! `AT_HWCAP`/`AT_HWCAP2` auxiliary vector entries and the RISC-V
+//! `riscv_hwprobe` syscall, for `no_std` binaries which still link to libc.
+//!
+//! This is used automatically by the generated code when the `auxv` feature
+//! is enabled and the `std` feature is disabled, but it can also be used as a
+//! custom detection backend with `detect = maybe_special::auxv::detect`. On
+//! other operating systems, every feature is reported as unsupported.
+//!
+//! The kernel does not report every known feature, and those it does not are
+//! always reported as unsupported. The macro rejects specialisations on them
+//! when this backend is selected automatically.
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use core::ffi::c_ulong;
+
+    pub const AT_HWCAP: c_ulong = 16;
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm64ec"))]
+    pub const AT_HWCAP2: c_ulong = 26;
+
+    unsafe extern "C" {
+        fn getauxval(kind: c_ulong) -> c_ulong;
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        pub fn syscall(number: core::ffi::c_long, ...) -> core::ffi::c_long;
+    }
+
+    /// Reads an entry of the auxiliary vector, which is zero if it is missing.
+    pub fn auxval(kind: c_ulong) -> u64 {
+        // SAFETY: `getauxval` has no preconditions.
+        unsafe { getauxval(kind) as u64 }
+    }
+}
+
+/// Returns whether a target feature is supported by the CPU and enabled by
+/// the kernel. Unknown features are always reported as unsupported.
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "aarch64", target_arch = "arm64ec")
+))]
+pub fn detect(feature: &str) -> bool {
+    let hwcap = sys::auxval(sys::AT_HWCAP);
+    let hwcap2 = sys::auxval(sys::AT_HWCAP2);
+    let bit = |bit: u32| hwcap & (1 << bit) != 0;
+    let bit2 = |bit: u32| hwcap2 & (1 << bit) != 0;
+
+    match feature {
+        "fp" => bit(0),
+        "asimd" | "neon" => bit(0) && bit(1),
+        "aes" => bit(3) && bit(4),
+        "pmull" => bit(4),
+        "sha2" => bit(5) && bit(6),
+        "crc" => bit(7),
+        "lse" => bit(8),
+        "fp16" => bit(9) && bit(10),
+        "rdm" => bit(12),
+        "jsconv" => bit(13),
+        "fcma" => bit(14),
+        "rcpc" => bit(15),
+        "dpb" => bit(16),
+        "sha3" => bit(5) && bit(6) && bit(17) && bit(21),
+        "sm4" => bit(18) && bit(19),
+        "dotprod" => bit(20),
+        "sve" => bit(22),
+        "fhm" => bit(23),
+        "dit" => bit(24),
+        "lse2" => bit(25),
+        "rcpc2" => bit(26),
+        "flagm" => bit(27),
+        "ssbs" => bit(28),
+        "sb" => bit(29),
+        "paca" => bit(30),
+        "pacg" => bit(31),
+
+        "dpb2" => bit2(0),
+        "sve2" => bit2(1),
+        "sve2-aes" => bit2(2) && bit2(3),
+        "sve2-bitperm" => bit2(4),
+        "sve2-sha3" => bit2(5),
+        "sve2-sm4" => bit2(6),
+        "flagm2" => bit2(7),
+        "frintts" => bit2(8),
+        "f32mm" => bit2(10),
+        "f64mm" => bit2(11),
+        "i8mm" => bit2(13),
+        "bf16" => bit2(14),
+        "rand" => bit2(16),
+        "bti" => bit2(17),
+        "mte" => bit2(18),
+        "ecv" => bit2(19),
+        "sme" => bit2(23),
+        "sme-i16i64" => bit2(24),
+        "sme-f64f64" => bit2(25),
+        "sme-fa64" => bit2(30),
+        "wfxt" => bit2(31),
+        "cssc" => bit2(34),
+        "sve2p1" => bit2(36),
+        "sme2" => bit2(37),
+        "sme2p1" => bit2(38),
+        "sme-b16b16" => bit2(41),
+        "sme-f16f16" => bit2(42),
+        "mops" => bit2(43),
+        "hbc" => bit2(44),
+        "sve-b16b16" => bit2(45),
+        "rcpc3" => bit2(46),
+        "lse128" => bit2(47),
+        "fpmr" => bit2(48),
+        "lut" => bit2(49),
+        "faminmax" => bit2(50),
+        "fp8" => bit2(51),
+        "fp8fma" => bit2(52),
+        "fp8dot4" => bit2(53),
+        "fp8dot2" => bit2(54),
+        "sme-lutv2" => bit2(57),
+        "sme-f8f16" => bit2(58),
+        "sme-f8f32" => bit2(59),
+        "ssve-fp8fma" => bit2(60),
+        "ssve-fp8dot4" => bit2(61),
+        "ssve-fp8dot2" => bit2(62),
+
+        _ => false,
+    }
+}
+
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "riscv32", target_arch = "riscv64")
+))]
+mod hwprobe {
+    use core::ffi::c_long;
+
+    const SYS_RISCV_HWPROBE: c_long = 258;
+
+    pub const KEY_BASE_BEHAVIOR: i64 = 3;
+    pub const KEY_IMA_EXT_0: i64 = 4;
+    pub const KEY_MISALIGNED_SCALAR_PERF: i64 = 9;
+    pub const KEY_MISALIGNED_VECTOR_PERF: i64 = 10;
+
+    pub const BASE_BEHAVIOR_IMA: u64 = 1 << 0;
+    pub const MISALIGNED_FAST: u64 = 3;
+
+    #[repr(C)]
+    pub struct Pair {
+        pub key: i64,
+        pub value: u64,
+    }
+
+    /// Queries every pair from the kernel, returning `false` if the syscall is
+    /// not supported. Keys unknown to the kernel are set to `-1`.
+    pub fn probe(pairs: &mut [Pair]) -> bool {
+        // SAFETY: The pairs are valid for the given length, and no CPU set is
+        // given, so the values are those supported by every CPU.
+        let result = unsafe {
+            super::sys::syscall(
+                SYS_RISCV_HWPROBE,
+                pairs.as_mut_ptr(),
+                pairs.len(),
+                0usize,
+                core::ptr::null_mut::<u8>(),
+                0u32,
+            )
+        };
+
+        result == 0
+    }
+}
+
+/// Returns whether a target feature is supported by the CPU and enabled by
+/// the kernel. Unknown features are always reported as unsupported.
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "riscv32", target_arch = "riscv64")
+))]
+pub fn detect(feature: &str) -> bool {
+    use hwprobe::*;
+
+    // Single letter extensions are set in `AT_HWCAP` by their position in the
+    // alphabet.
+    let hwcap = sys::auxval(sys::AT_HWCAP);
+    let letter = |letter: u8| hwcap & (1 << (letter - b'a')) != 0;
+
+    let mut pairs = [
+        Pair {
+            key: KEY_BASE_BEHAVIOR,
+            value: 0,
+        },
+        Pair {
+            key: KEY_IMA_EXT_0,
+            value: 0,
+        },
+        Pair {
+            key: KEY_MISALIGNED_SCALAR_PERF,
+            value: 0,
+        },
+        Pair {
+            key: KEY_MISALIGNED_VECTOR_PERF,
+            value: 0,
+        },
+    ];
+
+    // Older kernels do not support `riscv_hwprobe`, in which case only the
+    // single letter extensions can be detected.
+    let value = |pair: &Pair| if pair.key < 0 { 0 } else { pair.value };
+    let [base, ext, scalar, vector] = if probe(&mut pairs) {
+        [
+            value(&pairs[0]),
+            value(&pairs[1]),
+            value(&pairs[2]),
+            value(&pairs[3]),
+        ]
+    } else {
+        [0; 4]
+    };
+
+    let ima = base & BASE_BEHAVIOR_IMA != 0;
+    let bit = |bit: u32| ext & (1 << bit) != 0;
+
+    let zbkb = bit(8);
+    let zbkc = bit(9);
+    let zbkx = bit(10);
+    let zkn = zbkb && zbkc && zbkx && bit(11) && bit(12) && bit(13);
+    let zks = zbkb && zbkc && zbkx && bit(14) && bit(15);
+    let zvkn = bit(21) && bit(23) && bit(19) && bit(26);
+    let zvks = bit(24) && bit(25) && bit(19) && bit(26);
+
+    match feature {
+        "rv32i" => cfg!(target_arch = "riscv32") && (ima || letter(b'i')),
+        "rv64i" => cfg!(target_arch = "riscv64") && (ima || letter(b'i')),
+        "zicsr" | "zifencei" => ima,
+        "m" => ima || letter(b'm'),
+        "a" => ima || letter(b'a'),
+        "zaamo" => ima || letter(b'a') || bit(56),
+        "zalrsc" => ima || letter(b'a') || bit(57),
+        "f" => letter(b'f') || bit(0),
+        "d" => letter(b'd') || bit(0),
+        "c" => letter(b'c') || bit(1),
+        "zca" => letter(b'c') || bit(1) || bit(43),
+        "v" => letter(b'v') || bit(2),
+
+        "zba" => bit(3),
+        "zbb" => bit(4),
+        "zbs" => bit(5),
+        "b" => bit(3) && bit(4) && bit(5),
+        "zicboz" => bit(6),
+        "zbc" => bit(7),
+        "zbkb" => zbkb,
+        "zbkc" => zbkc,
+        "zbkx" => zbkx,
+        "zknd" => bit(11),
+        "zkne" => bit(12),
+        "zknh" => bit(13),
+        "zksed" => bit(14),
+        "zksh" => bit(15),
+        "zkt" => bit(16),
+        "zkn" => zkn,
+        "zks" => zks,
+        "zvbb" => bit(17),
+        "zvbc" => bit(18),
+        "zvkb" => bit(19),
+        "zvkg" => bit(20),
+        "zvkned" => bit(21),
+        "zvknha" => bit(22),
+        "zvknhb" => bit(23),
+        "zvksed" => bit(24),
+        "zvksh" => bit(25),
+        "zvkt" => bit(26),
+        "zvkn" => zvkn,
+        "zvknc" => zvkn && bit(18),
+        "zvkng" => zvkn && bit(20),
+        "zvks" => zvks,
+        "zvksc" => zvks && bit(18),
+        "zvksg" => zvks && bit(20),
+        "zfh" => bit(27),
+        "zfhmin" => bit(28),
+        "zihintntl" => bit(29),
+        "zvfh" => bit(30),
+        "zvfhmin" => bit(31),
+        "zfa" => bit(32),
+        "ztso" => bit(33),
+        "zacas" => bit(34),
+        "zicond" => bit(35),
+        "zihintpause" => bit(36),
+        "zve32x" => bit(37),
+        "zve32f" => bit(38),
+        "zve64x" => bit(39),
+        "zve64f" => bit(40),
+        "zve64d" => bit(41),
+        "zimop" => bit(42),
+        "zcb" => bit(44),
+        "zcd" => bit(45),
+        "zcf" => bit(46),
+        "zcmop" => bit(47),
+        "zawrs" => bit(48),
+        "zicntr" => bit(50),
+        "zihpm" => bit(51),
+        "zfbfmin" => bit(52),
+        "zvfbfmin" => bit(53),
+        "zvfbfwma" => bit(54),
+        "zicbom" => bit(55),
+        "zabha" => bit(58),
+
+        "unaligned-scalar-mem" => scalar == MISALIGNED_FAST,
+        "unaligned-vector-mem" => vector == MISALIGNED_FAST,
+
+        _ => false,
+    }
+}
+
+/// Returns whether a target feature is supported. This operating system has
+/// no auxiliary vector, so every feature is reported as unsupported.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn detect(_feature: &str) -> bool {
+    false
+}
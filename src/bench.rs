@@ -0,0 +1,80 @@
+use crate::Variant;
+use core::{fmt, hint::black_box, time::Duration};
+use std::{time::Instant, vec::Vec};
+
+/// The time taken by a single specialisation in a generated benchmark, see the
+/// [crate-level documentation](crate#benchmarking-every-specialisation).
+#[derive(Clone, Copy, Debug)]
+pub struct Timing {
+    variant: Variant,
+    total: Duration,
+    calls: u64,
+}
+
+impl Timing {
+    /// Returns the specialisation which was timed.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Returns the total time spent in the specialisation.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Returns the number of times the specialisation was called.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Returns the mean time taken by a single call.
+    pub fn per_call(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_secs_f64(self.total.as_secs_f64() / calls as f64),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:?} per call ({} calls)",
+            self.variant,
+            self.per_call(),
+            self.calls
+        )
+    }
+}
+
+/// Times `func` over `iterations` passes of every input. Each pass is cloned
+/// from `inputs` before the timer starts, so cloning is not measured.
+#[doc(hidden)]
+pub fn bench<I: Clone>(
+    variant: Variant,
+    inputs: &[I],
+    iterations: u32,
+    mut func: impl FnMut(I),
+) -> Timing {
+    // Warm up the caches and the branch predictor before timing anything.
+    for input in inputs.iter().cloned() {
+        func(black_box(input));
+    }
+
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let pass: Vec<I> = inputs.to_vec();
+        let start = Instant::now();
+        for input in pass {
+            func(black_box(input));
+        }
+        total += start.elapsed();
+    }
+
+    Timing {
+        variant,
+        total,
+        calls: iterations as u64 * inputs.len() as u64,
+    }
+}
@@ -0,0 +1,76 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The maximum number of target features that can be cached for a single
+/// architecture.
+pub const MAX_FEATURES: usize = 128;
+
+const UNKNOWN: u8 = 0;
+const ABSENT: u8 = 1;
+const PRESENT: u8 = 2;
+
+/// Caches the results of run-time feature detection, so that every
+/// specialised function in a binary shares a single detection result for each
+/// feature, rather than running detection itself.
+///
+/// Features are identified by their index in the table of known features for
+/// the current architecture, which is assigned by the macro.
+pub struct FeatureCache {
+    states: [AtomicU8; MAX_FEATURES],
+}
+
+impl FeatureCache {
+    pub const fn new() -> Self {
+        Self {
+            states: [const { AtomicU8::new(UNKNOWN) }; MAX_FEATURES],
+        }
+    }
+
+    /// Returns whether `feature` is available, calling `detect` and caching
+    /// its result if it has not been detected yet. `index` is the index of
+    /// `feature` in the table of known features, and `implied` lists the
+    /// features it implies.
+    #[inline]
+    pub fn test(
+        &self,
+        index: usize,
+        feature: &str,
+        implied: &[&str],
+        detect: impl FnOnce() -> bool,
+    ) -> bool {
+        match self.states[index].load(Ordering::Relaxed) {
+            PRESENT => true,
+            ABSENT => false,
+            _ => {
+                let detected = self::detect(feature, implied, detect);
+                self.states[index]
+                    .store(if detected { PRESENT } else { ABSENT }, Ordering::Relaxed);
+                detected
+            }
+        }
+    }
+}
+
+impl Default for FeatureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Detects whether `feature` is available without caching the result.
+///
+/// With the `env` feature enabled, features can also be disabled through the
+/// `MAYBE_SPECIAL_DISABLE` and `MAYBE_SPECIAL_FORCE` environment variables,
+/// which also disables `feature` when any of the `implied` features are.
+#[inline]
+#[cfg_attr(not(feature = "env"), allow(unused_variables))]
+pub fn detect(feature: &str, implied: &[&str], detect: impl FnOnce() -> bool) -> bool {
+    #[cfg(feature = "env")]
+    if !crate::env::allowed(feature, implied) {
+        return false;
+    }
+
+    detect()
+}
+
+/// The feature cache shared by every specialised function.
+pub static FEATURES: FeatureCache = FeatureCache::new();
@@ -0,0 +1,335 @@
+//! Run-time feature detection on x86 and x86_64 using the `cpuid` and
+//! `xgetbv` instructions directly, for `no_std` targets on stable Rust.
+//!
+//! This is used automatically by the generated code when the `cpuid` feature
+//! is enabled and the `std` feature is disabled, but it can also be used as a
+//! custom detection backend with `detect = maybe_special::cpuid::detect`.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid_count, _xgetbv};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid_count, _xgetbv};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// The registers returned by `cpuid`, in the order `eax`, `ebx`, `ecx`, `edx`.
+type Registers = [u32; 4];
+
+// Bits of the `XCR0` register, which the OS sets when it saves the state of
+// each register set on context switches.
+const XCR0_AVX: u32 = 0b110;
+const XCR0_AVX512: u32 = 0b1110_0110;
+const XCR0_AMX: u32 = 0b11 << 17;
+const XCR0_APX: u32 = 1 << 19;
+
+/// The number of registers used by [`detect`], see [`read_leaves`].
+const LEAF_REGISTERS: usize = 13;
+
+static LEAVES: [AtomicU32; LEAF_REGISTERS] = [const { AtomicU32::new(0) }; LEAF_REGISTERS];
+static LEAVES_READ: AtomicBool = AtomicBool::new(false);
+
+fn cpuid(leaf: u32, sub_leaf: u32) -> Registers {
+    // `__cpuid_count` is only safe in newer versions of Rust.
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid_count(leaf, sub_leaf) };
+    [result.eax, result.ebx, result.ecx, result.edx]
+}
+
+/// Returns the leaf if it is supported by the CPU, otherwise all zeroes.
+fn leaf(max: u32, leaf: u32, sub_leaf: u32) -> Registers {
+    if leaf <= max {
+        cpuid(leaf, sub_leaf)
+    } else {
+        [0; 4]
+    }
+}
+
+/// Returns whether the CPU supports `cpuid`, which some 32-bit CPUs do not.
+/// `core::arch::x86::has_cpuid` is unstable, so this does the same check of
+/// whether the ID bit of `EFLAGS` can be toggled.
+#[cfg(target_arch = "x86")]
+fn has_cpuid() -> bool {
+    // Every CPU with SSE supports `cpuid`.
+    if cfg!(target_feature = "sse") {
+        return true;
+    }
+
+    let before: u32;
+    let after: u32;
+    // SAFETY: This only toggles the ID bit of `EFLAGS` and then restores it,
+    // leaving the stack as it was.
+    unsafe {
+        core::arch::asm!(
+            "pushfd",
+            "pop {before}",
+            "mov {after}, {before}",
+            "xor {after}, 0x200000",
+            "push {after}",
+            "popfd",
+            "pushfd",
+            "pop {after}",
+            "push {before}",
+            "popfd",
+            before = out(reg) before,
+            after = out(reg) after,
+        );
+    }
+
+    (before ^ after) & 0x20_0000 != 0
+}
+
+/// Reads every register used by [`detect`], along with the low half of
+/// `XCR0`. These are all zero if the CPU does not support `cpuid`.
+fn read_leaves() -> [u32; LEAF_REGISTERS] {
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return [0; LEAF_REGISTERS];
+    }
+
+    let max_leaf = cpuid(0, 0)[0];
+    let max_extended_leaf = cpuid(0x8000_0000, 0)[0];
+
+    let [_, _, ecx1, edx1] = leaf(max_leaf, 1, 0);
+    let [_, ebx7, ecx7, edx7] = leaf(max_leaf, 7, 0);
+    let [eax7_1, _, _, edx7_1] = leaf(max_leaf, 7, 1);
+    let [eax_d1, _, _, _] = leaf(max_leaf, 0xd, 1);
+    let [_, ebx19, _, _] = leaf(max_leaf, 0x19, 0);
+    let [eax1e_1, _, _, _] = leaf(max_leaf, 0x1e, 1);
+    let [_, ebx24, _, _] = leaf(max_leaf, 0x24, 0);
+    let [_, _, ecx_ext1, _] = leaf(max_extended_leaf, 0x8000_0001, 0);
+
+    let xcr0 = if bit(ecx1, 27) {
+        // SAFETY: The OS has enabled `xgetbv`, as `OSXSAVE` is set.
+        unsafe { _xgetbv(0) as u32 }
+    } else {
+        0
+    };
+
+    [
+        ecx1, edx1, ebx7, ecx7, edx7, eax7_1, edx7_1, eax_d1, ebx19, eax1e_1, ebx24, ecx_ext1, xcr0,
+    ]
+}
+
+/// Returns the registers read by [`read_leaves`], which are only read the
+/// first time this is called.
+fn leaves() -> [u32; LEAF_REGISTERS] {
+    if !LEAVES_READ.load(Ordering::Acquire) {
+        for (cached, register) in LEAVES.iter().zip(read_leaves()) {
+            cached.store(register, Ordering::Relaxed);
+        }
+
+        LEAVES_READ.store(true, Ordering::Release);
+    }
+
+    LEAVES
+        .each_ref()
+        .map(|register| register.load(Ordering::Relaxed))
+}
+
+fn bit(register: u32, bit: u32) -> bool {
+    register & (1 << bit) != 0
+}
+
+/// Returns whether a target feature is supported by the CPU, and enabled by
+/// the OS if it requires saving extra registers. Unknown features are always
+/// reported as unsupported.
+pub fn detect(feature: &str) -> bool {
+    let [
+        ecx1,
+        edx1,
+        ebx7,
+        ecx7,
+        edx7,
+        eax7_1,
+        edx7_1,
+        eax_d1,
+        ebx19,
+        eax1e_1,
+        ebx24,
+        ecx_ext1,
+        xcr0,
+    ] = leaves();
+
+    let os_xsave = bit(ecx1, 27);
+    let os_avx = xcr0 & XCR0_AVX == XCR0_AVX;
+    let os_avx512 = xcr0 & XCR0_AVX512 == XCR0_AVX512;
+    let os_amx = xcr0 & XCR0_AMX == XCR0_AMX;
+    let os_apx = xcr0 & XCR0_APX == XCR0_APX;
+
+    match feature {
+        "tsc" => bit(edx1, 4),
+        "mmx" => bit(edx1, 23),
+        "fxsr" => bit(edx1, 24),
+        "sse" => bit(edx1, 25),
+        "sse2" => bit(edx1, 26),
+        "sse3" => bit(ecx1, 0),
+        "pclmulqdq" => bit(ecx1, 1),
+        "ssse3" => bit(ecx1, 9),
+        "cmpxchg16b" => bit(ecx1, 13),
+        "sse4.1" => bit(ecx1, 19),
+        "sse4.2" => bit(ecx1, 20),
+        "movbe" => bit(ecx1, 22),
+        "popcnt" => bit(ecx1, 23),
+        "aes" => bit(ecx1, 25),
+        "xsave" => os_xsave && bit(ecx1, 26),
+        "rdrand" => bit(ecx1, 30),
+
+        "avx" => os_avx && bit(ecx1, 28),
+        "fma" => os_avx && bit(ecx1, 12),
+        "f16c" => os_avx && bit(ecx1, 29),
+
+        "bmi1" => bit(ebx7, 3),
+        "avx2" => os_avx && bit(ebx7, 5),
+        "bmi2" => bit(ebx7, 8),
+        "ermsb" => bit(ebx7, 9),
+        "rtm" => bit(ebx7, 11),
+        "rdseed" => bit(ebx7, 18),
+        "adx" => bit(ebx7, 19),
+        "sha" => bit(ebx7, 29),
+        "gfni" => bit(ecx7, 8),
+        "vaes" => os_avx && bit(ecx7, 9),
+        "vpclmulqdq" => os_avx && bit(ecx7, 10),
+        "kl" => bit(ecx7, 23) && bit(ebx19, 0),
+        "widekl" => bit(ecx7, 23) && bit(ebx19, 0) && bit(ebx19, 2),
+
+        "avx512f" => os_avx512 && bit(ebx7, 16),
+        "avx512dq" => os_avx512 && bit(ebx7, 17),
+        "avx512ifma" => os_avx512 && bit(ebx7, 21),
+        "avx512pf" => os_avx512 && bit(ebx7, 26),
+        "avx512er" => os_avx512 && bit(ebx7, 27),
+        "avx512cd" => os_avx512 && bit(ebx7, 28),
+        "avx512bw" => os_avx512 && bit(ebx7, 30),
+        "avx512vl" => os_avx512 && bit(ebx7, 31),
+        "avx512vbmi" => os_avx512 && bit(ecx7, 1),
+        "avx512vbmi2" => os_avx512 && bit(ecx7, 6),
+        "avx512vnni" => os_avx512 && bit(ecx7, 11),
+        "avx512bitalg" => os_avx512 && bit(ecx7, 12),
+        "avx512vpopcntdq" => os_avx512 && bit(ecx7, 14),
+        "avx512vp2intersect" => os_avx512 && bit(edx7, 8),
+        "avx512fp16" => os_avx512 && bit(edx7, 23),
+        "avx512bf16" => os_avx512 && bit(eax7_1, 5),
+
+        "sha512" => os_avx && bit(eax7_1, 0),
+        "sm3" => os_avx && bit(eax7_1, 1),
+        "sm4" => os_avx && bit(eax7_1, 2),
+        "avxvnni" => os_avx && bit(eax7_1, 4),
+        "avxifma" => os_avx && bit(eax7_1, 23),
+        "movrs" => bit(eax7_1, 31),
+        "avxvnniint8" => os_avx && bit(edx7_1, 4),
+        "avxneconvert" => os_avx && bit(edx7_1, 5),
+        "avxvnniint16" => os_avx && bit(edx7_1, 10),
+        "apxf" => os_apx && bit(edx7_1, 21),
+
+        "avx10.1" => os_avx512 && bit(edx7_1, 19) && ebx24 & 0xff >= 1,
+        "avx10.2" => os_avx512 && bit(edx7_1, 19) && ebx24 & 0xff >= 2,
+
+        "amx-bf16" => os_amx && bit(edx7, 22),
+        "amx-tile" => os_amx && bit(edx7, 24),
+        "amx-int8" => os_amx && bit(edx7, 25),
+        "amx-fp16" => os_amx && bit(eax7_1, 21),
+        "amx-complex" => os_amx && bit(edx7_1, 8),
+        "amx-fp8" => os_amx && bit(eax1e_1, 4),
+        "amx-transpose" => os_amx && bit(eax1e_1, 5),
+        "amx-tf32" => os_amx && bit(eax1e_1, 6),
+        "amx-avx512" => os_amx && os_avx512 && bit(eax1e_1, 7),
+        "amx-movrs" => os_amx && bit(eax1e_1, 8),
+
+        "xsaveopt" => os_xsave && bit(eax_d1, 0),
+        "xsavec" => os_xsave && bit(eax_d1, 1),
+        "xsaves" => os_xsave && bit(eax_d1, 3),
+
+        "lzcnt" => bit(ecx_ext1, 5),
+        "sse4a" => bit(ecx_ext1, 6),
+        "xop" => os_avx && bit(ecx_ext1, 11),
+        "tbm" => bit(ecx_ext1, 21),
+
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "cpuid", target_arch = "x86_64"))]
+mod tests {
+    extern crate std;
+
+    macro_rules! assert_matches_std {
+        ($($feature:tt),* $(,)?) => {
+            $(
+                assert_eq!(
+                    super::detect($feature),
+                    std::is_x86_feature_detected!($feature),
+                    "{} does not match std_detect",
+                    $feature
+                );
+            )*
+        };
+    }
+
+    /// Compares every feature which `is_x86_feature_detected!` accepts on
+    /// stable Rust.
+    #[test]
+    fn detect_matches_std() {
+        assert_matches_std!(
+            "tsc",
+            "mmx",
+            "fxsr",
+            "sse",
+            "sse2",
+            "sse3",
+            "pclmulqdq",
+            "ssse3",
+            "cmpxchg16b",
+            "sse4.1",
+            "sse4.2",
+            "movbe",
+            "popcnt",
+            "aes",
+            "xsave",
+            "rdrand",
+            "avx",
+            "fma",
+            "f16c",
+            "bmi1",
+            "avx2",
+            "bmi2",
+            "ermsb",
+            "rtm",
+            "rdseed",
+            "adx",
+            "sha",
+            "gfni",
+            "vaes",
+            "vpclmulqdq",
+            "kl",
+            "widekl",
+            "avx512f",
+            "avx512dq",
+            "avx512ifma",
+            "avx512pf",
+            "avx512er",
+            "avx512cd",
+            "avx512bw",
+            "avx512vl",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512vnni",
+            "avx512bitalg",
+            "avx512vpopcntdq",
+            "avx512vp2intersect",
+            "avx512fp16",
+            "avx512bf16",
+            "sha512",
+            "sm3",
+            "sm4",
+            "avxvnni",
+            "avxifma",
+            "avxvnniint8",
+            "avxneconvert",
+            "avxvnniint16",
+            "xsaveopt",
+            "xsavec",
+            "xsaves",
+            "lzcnt",
+            "sse4a",
+            "tbm",
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use core::{
+    marker::PhantomData,
+    mem::transmute_copy,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// Holds the function pointer called by function pointer dispatch, which
+/// starts as the initialiser and is replaced by the selected specialisation.
+///
+/// The pointer is stored type-erased, as specialisations can only be cast to
+/// `*mut ()` rather than to the safe function pointer type `F` of the outer
+/// function.
+pub struct DispatchSlot<F> {
+    ptr: AtomicPtr<()>,
+    _fn: PhantomData<F>,
+}
+
+impl<F: Copy> DispatchSlot<F> {
+    /// Creates a slot which initially points to `init`.
+    ///
+    /// # Safety
+    /// `F` must be a function pointer type, and `init` must point to a function
+    /// which can be called as `F`.
+    pub const unsafe fn new(init: *mut ()) -> Self {
+        assert!(size_of::<F>() == size_of::<*mut ()>());
+
+        Self {
+            ptr: AtomicPtr::new(init),
+            _fn: PhantomData,
+        }
+    }
+
+    /// Returns the function which is currently selected.
+    #[inline(always)]
+    pub fn get(&self) -> F {
+        // SAFETY: Every pointer stored in the slot can be called as `F`.
+        unsafe { cast(self.ptr.load(Ordering::Relaxed)) }
+    }
+
+    /// Replaces the selected function.
+    ///
+    /// # Safety
+    /// `func` must point to a function which can be called as `F`.
+    #[inline(always)]
+    pub unsafe fn set(&self, func: *mut ()) {
+        self.ptr.store(func, Ordering::Relaxed);
+    }
+}
+
+/// Casts a type-erased function pointer back to its function pointer type.
+///
+/// # Safety
+/// `F` must be a function pointer type, and `func` must point to a function
+/// which can be called as `F`.
+#[inline(always)]
+pub unsafe fn cast<F: Copy>(func: *mut ()) -> F {
+    assert!(size_of::<F>() == size_of::<*mut ()>());
+
+    // SAFETY: `F` is the same size as a pointer, as checked above.
+    unsafe { transmute_copy::<*mut (), F>(&func) }
+}
@@ -0,0 +1,111 @@
+use std::env;
+
+/// A comma separated list of features that are never used.
+const DISABLE_VAR: &str = "MAYBE_SPECIAL_DISABLE";
+
+/// The name of a variant, such as `generic` or `x86+avx2,fma`, whose features
+/// are the only ones that may be used.
+const FORCE_VAR: &str = "MAYBE_SPECIAL_FORCE";
+
+/// Returns whether `feature` is allowed to be used by the environment, where
+/// `implied` lists the features it implies. This can only ever disable
+/// features, as enabling a feature that is not available is undefined
+/// behaviour.
+pub fn allowed(feature: &str, implied: &[&str]) -> bool {
+    let disable = env::var(DISABLE_VAR).ok();
+    let force = env::var(FORCE_VAR).ok();
+    is_allowed(feature, implied, disable.as_deref(), force.as_deref())
+}
+
+/// Returns whether `feature` is allowed by the given values of the
+/// environment variables. A forced variant takes precedence over any disabled
+/// features, and empty values are treated as if they were not set. Disabling a
+/// feature also disables every feature which implies it.
+fn is_allowed(feature: &str, implied: &[&str], disable: Option<&str>, force: Option<&str>) -> bool {
+    match force.map(str::trim).filter(|forced| !forced.is_empty()) {
+        Some(forced) => {
+            let features = match forced.split_once('+') {
+                Some((_arch, features)) => features,
+                None if forced == "generic" => "",
+                None => forced,
+            };
+
+            split(features).any(|forced| forced == feature)
+        }
+        None => disable.is_none_or(|disabled| {
+            split(disabled).all(|disabled| disabled != feature && !implied.contains(&disabled))
+        }),
+    }
+}
+
+fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everything_is_allowed_without_variables() {
+        assert!(is_allowed("avx2", &[], None, None));
+    }
+
+    #[test]
+    fn disabled_features_are_split_at_commas() {
+        let disable = Some(" avx512f ,avx2,, fma ");
+        assert!(!is_allowed("avx512f", &[], disable, None));
+        assert!(!is_allowed("avx2", &[], disable, None));
+        assert!(!is_allowed("fma", &[], disable, None));
+        assert!(is_allowed("sse4.1", &[], disable, None));
+        assert!(is_allowed("avx", &[], disable, None));
+    }
+
+    #[test]
+    fn disabled_features_disable_features_implying_them() {
+        let implied = &["avx2", "avx", "fma", "sse4.2"];
+        assert!(!is_allowed("avx512f", implied, Some("avx2"), None));
+        assert!(is_allowed("avx512f", implied, Some("avx512bw"), None));
+        assert!(is_allowed(
+            "avx512f",
+            implied,
+            Some("avx2"),
+            Some("x86+avx512f")
+        ));
+    }
+
+    #[test]
+    fn forced_variant_only_allows_its_features() {
+        let force = Some(" x86+avx2, fma ");
+        assert!(is_allowed("avx2", &[], None, force));
+        assert!(is_allowed("fma", &[], None, force));
+        assert!(!is_allowed("avx512f", &[], None, force));
+        assert!(!is_allowed("x86", &[], None, force));
+    }
+
+    #[test]
+    fn forced_generic_allows_nothing() {
+        assert!(!is_allowed("avx2", &[], None, Some("generic")));
+        assert!(!is_allowed("generic", &[], None, Some("generic")));
+    }
+
+    #[test]
+    fn empty_values_are_ignored() {
+        assert!(is_allowed("avx2", &[], Some(""), None));
+        assert!(is_allowed("avx2", &[], Some(" , "), None));
+        assert!(is_allowed("avx2", &[], None, Some("")));
+        assert!(is_allowed("avx2", &[], None, Some("  ")));
+        assert!(!is_allowed("avx2", &[], Some("avx2"), Some("")));
+    }
+
+    #[test]
+    fn forced_variant_takes_precedence_over_disabled_features() {
+        let disable = Some("avx2,fma");
+        let force = Some("x86+avx2");
+        assert!(is_allowed("avx2", &[], disable, force));
+        assert!(!is_allowed("fma", &[], disable, force));
+        assert!(!is_allowed("avx512f", &[], disable, force));
+    }
+}
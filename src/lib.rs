@@ -26,6 +26,24 @@
 //! specialisations can be marked with `static` to enable static dispatch on
 //! them, which is explained below.
 //!
+//! The supported architectures are `x86` (which covers both `x86` and
+//! `x86_64`), `aarch64` (which also covers `arm64ec`), `arm`, `riscv`,
+//! `loongarch64`, `loongarch32`, `mips`, `mips64`, `powerpc`, `powerpc64` and
+//! `s390x`, along with the [architectures without run-time
+//! detection](#architectures-without-run-time-detection).
+//!
+//! Every feature is checked against the features which can be detected on
+//! its architecture, so a typo is reported as an error even when compiling
+//! for a different architecture.
+//!
+//! ```compile_fail
+//! // error: avx512fl is not a known x86 feature, did you mean avx512f?
+//! #[maybe_special::make_special(x86 = ["avx512fl"])]
+//! pub fn sum(a: &[u32]) -> u32 {
+//!     a.iter().sum()
+//! }
+//! ```
+//!
 //! <h5>Usage notes</h5>
 //!
 //! - This macro does not figure out which specialisations are most optimal for
@@ -34,11 +52,15 @@
 //!   significant performance overhead when applied improperly. This macro adds
 //!   an atomic memory read and creates an inline boundary for every function it
 //!   is applied to. Additionally, the initialisation run upon the first call of
-//!   the function can be comparatively quite slow due to performance issues
-//!   with [`std::arch`]/[`std_detect`]'s `is_*_feature_detected` macros.
+//!   the function must select a specialisation. The results of
+//!   [`std::arch`]/[`std_detect`]'s `is_*_feature_detected` macros are cached
+//!   and shared between every specialised function in a binary, so each feature
+//!   is only ever detected once.
 //! - This macro can only specialise any function it is applied to. If a
 //!   function calls another function which isn't inlined, that callee will not
-//!   be specialised.
+//!   be specialised, unless it is passed a feature token or is listed as a
+//!   callee (see [propagating features to
+//!   callees](#propagating-features-to-callees)).
 //! - Under the hood this macro uses the [`#[target_feature]`](https://doc.rust-lang.org/reference/attributes/codegen.html#the-target_feature-attribute)
 //!   attribute which tells LLVM to output code as if those features were
 //!   enabled. However, it seems there is a bug where any form of [LTO] undoes
@@ -59,23 +81,196 @@
 //! }
 //! ```
 //!
-//! # Use on types that use `self`/`Self`
-//! To allow this macro to work anywhere it must generate the specialisations
-//! inside the outer function, however this has the side-effect of not working
-//! for types that use `self`/`Self` (because the inner function doesn't know
-//! what `Self` is).
+//! <h5>Microarchitecture levels</h5>
+//!
+//! Instead of listing every feature by hand, a specialisation can name a
+//! microarchitecture level in the form `arch = level("name")`, which expands
+//! into every feature required by that level. Levels can also be mixed with
+//! other features, e.g. `x86 = [level("x86-64-v3"), "vaes"]`. Only features
+//! which can be detected at run-time are included. The known levels are:
+//!
+//! - `x86`: the psABI levels `x86-64-v2`, `x86-64-v3` and `x86-64-v4`.
+//! - `aarch64`: `armv8-a` through `armv8.6-a`, `armv9-a` and `armv9.1-a`.
+//! - `riscv`: the `rva22u64` and `rva23u64` profiles.
+//!
+//! ```
+//! #[maybe_special::make_special(
+//!     x86 = level("x86-64-v4"),
+//!     x86 = level("x86-64-v3"),
+//!     aarch64 = [level("armv8.2-a"), "dotprod"],
+//!     riscv = level("rva23u64"),
+//! )]
+//! pub fn fast_dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//! ```
+//!
+//! <h5>Specialisation order</h5>
+//!
+//! Specialisations are checked in the order they are declared, and the first
+//! one supported by the CPU is selected. This macro knows which features
+//! imply each other (e.g. `avx512f` implies `avx2`), and emits a warning for
+//! any specialisation which can never be selected because an earlier one
+//! requires a subset of its features. Passing `order = auto` instead sorts the
+//! specialisations of each architecture so that every specialisation is
+//! checked before those whose features it implies.
 //!
-//! To get around this, you can do something like the following:
+//! A specialisation can also be given an explicit priority by putting
+//! `priority = N` after its feature set (and after its manual impl, if it has
+//! one). Specialisations with a higher priority are checked first, and those
+//! with the same priority keep their declared (or `order = auto`) order. The
+//! default priority is 0.
+//!
+//! ```
+//! #[maybe_special::make_special(
+//!     x86 = ["avx2"],
+//!     // Without `order = auto`, this would never be selected.
+//!     x86 = ["avx512f", "avx512vl"],
+//!     order = auto,
+//! )]
+//! pub fn fast_dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//! ```
+//!
+//! ```
+//! #[maybe_special::make_special(
+//!     x86 = ["avx512f", "avx512bw"],
+//!     // Prefer VAES on CPUs which support both, even though it is declared
+//!     // after AVX-512.
+//!     x86 = ["vaes"] priority = 1,
+//! )]
+//! pub fn checksum(a: &[u8]) -> u32 {
+//!     a.iter().map(|&a| a as u32).sum()
+//! }
 //! ```
+//!
+//! # Use on methods
+//! This macro can be applied directly to methods inside an inherent `impl`
+//! block. When the function takes `self` or mentions `Self`, the
+//! specialisations and dispatch functions are generated as hidden associated
+//! functions next to the method (prefixed with its name, e.g.
+//! `_clone_multiple_x86_avx2`) instead of inside of its body, so they are able
+//! to use `Self` as well. Any `#[cfg]` attributes on the method are copied
+//! onto these associated functions.
+//!
+//! ```
+//! #[derive(Clone)]
+//! pub struct SomeType(u32);
+//!
 //! impl SomeType {
-//!     fn clone_multiple(&self, num: usize) -> Vec<Self> {
-//!         #[maybe_special::make_special(x86 = ["avx2"])]
-//!         #[inline(always)]
-//!         fn inner(val: &SomeType, num: usize) -> Vec<SomeType> {
-//!             vec![val.clone(); num]
-//!         }
+//!     #[maybe_special::make_special(x86 = ["avx2"])]
+//!     pub fn clone_multiple(&self, num: usize) -> Vec<Self> {
+//!         vec![self.clone(); num]
+//!     }
+//!
+//!     #[maybe_special::make_special(x86 = ["avx2"])]
+//!     pub fn into_boxed(self: Box<Self>, num: u32) -> Box<Self> {
+//!         Box::new(Self(self.0 + num))
+//!     }
+//! }
+//! ```
+//!
+//! Methods always use jump table dispatch (see [Dispatch
+//! types](#dispatch-types)), as the dispatch statics are shared between every
+//! instantiation of a generic `impl`. Manual implementations are called as
+//! `Self::some_impl`, so they must also be associated functions of the same
+//! type.
+//!
+//! Trait `impl` blocks cannot contain any additional associated functions, so
+//! this macro cannot be applied to a single method inside of them. Instead,
+//! apply it to the whole `impl` block as shown below.
+//!
+//! <h5>Specialising a whole `impl` block</h5>
+//!
+//! This macro can also be applied to an entire `impl` block (including trait
+//! `impl` blocks), which specialises every method in it with the same feature
+//! set. A method can override this with its own `#[make_special(...)]`
+//! attribute, or opt out entirely with `#[make_special(skip)]`. Inside of a
+//! trait `impl` block, the generated functions are placed in a hidden helper
+//! trait which is implemented for the same type.
 //!
-//!         inner(self, num)
+//! ```
+//! # pub struct Kernels(Vec<f32>);
+//! #[maybe_special::make_special(x86 = ["avx2", "fma"], aarch64 = ["neon"])]
+//! impl Kernels {
+//!     pub fn sum(&self) -> f32 {
+//!         self.0.iter().sum()
+//!     }
+//!
+//!     #[make_special(x86 = ["avx512f"])]
+//!     pub fn max(&self) -> f32 {
+//!         self.0.iter().copied().fold(f32::MIN, f32::max)
+//!     }
+//!
+//!     #[make_special(skip)]
+//!     pub fn len(&self) -> usize {
+//!         self.0.len()
+//!     }
+//! }
+//! ```
+//!
+//! Generic trait `impl` blocks are supported as well, including generics
+//! which are only used by the trait.
+//!
+//! ```
+//! pub trait Scale<R> {
+//!     fn scale(&self, factor: R) -> Vec<f32>;
+//! }
+//!
+//! pub struct Samples(Vec<f32>);
+//!
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! impl<R: Into<f32> + Copy> Scale<R> for Samples {
+//!     fn scale(&self, factor: R) -> Vec<f32> {
+//!         self.0.iter().map(|sample| sample * factor.into()).collect()
+//!     }
+//! }
+//!
+//! assert_eq!(Samples(vec![1.0, 2.0]).scale(2u8), [2.0, 4.0]);
+//! ```
+//!
+//! Operator traits whose methods take `self` by value can be specialised in
+//! the same way.
+//!
+//! ```
+//! #[derive(Debug, PartialEq)]
+//! pub struct Vector(Vec<f32>);
+//!
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! impl core::ops::Mul<f32> for Vector {
+//!     type Output = Vector;
+//!
+//!     fn mul(self, rhs: f32) -> Vector {
+//!         Vector(self.0.into_iter().map(|x| x * rhs).collect())
+//!     }
+//! }
+//!
+//! assert_eq!(Vector(vec![1.0, 2.0]) * 2.0, Vector(vec![2.0, 4.0]));
+//! ```
+//!
+//! <h5>Specialising a whole module</h5>
+//!
+//! Applying this macro to an inline `mod` specialises every function and every
+//! `impl` block inside of it (including nested modules) with the same feature
+//! set. Feature detection is shared between all of them, so it only runs once
+//! per architecture for the whole module. Individual items can still override
+//! or opt out of the specialisations in the same way as above.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2", "fma"], aarch64 = ["neon"])]
+//! pub mod kernels {
+//!     pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+//!         a.iter().zip(b).map(|(a, b)| a * b).sum()
+//!     }
+//!
+//!     pub fn sum(a: &[f32]) -> f32 {
+//!         a.iter().sum()
+//!     }
+//!
+//!     #[make_special(skip)]
+//!     pub fn len(a: &[f32]) -> usize {
+//!         a.len()
 //!     }
 //! }
 //! ```
@@ -98,7 +293,7 @@
 //! ```
 //! fn dot_product_avx2(a: [u32; 16], b: [u32; 16]) -> u32 {
 //!     // Your impl here
-//!     ...
+//!     # a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
 //! }
 //!
 //! #[maybe_special::make_special(
@@ -111,12 +306,346 @@
 //! }
 //! ```
 //!
+//! # Propagating features to callees
+//! Passing `token = name` alongside the specialisations binds a zero-sized
+//! [`Token`] to `name` inside of the function body. Each specialisation gets
+//! its own token type, which can only be created once that specialisation is
+//! selected, and the generic impl gets a [`GenericToken`]. Helpers which take
+//! a token as a generic parameter are instantiated separately for each
+//! specialisation, and can call [`Token::vectorize`] to run code with its
+//! features enabled even when they are not inlined. [`Token::VARIANT`]
+//! describes the specialisation which created the token. Manual impls are
+//! called as they are, so they do not receive a token.
+//!
+//! ```
+//! use maybe_special::Token;
+//!
+//! #[inline(never)]
+//! fn scale<T: Token>(token: T, values: &mut [f32], by: f32) {
+//!     token.vectorize(|| {
+//!         for value in values {
+//!             *value *= by;
+//!         }
+//!     })
+//! }
+//!
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"], token = simd)]
+//! pub fn normalise(values: &mut [f32]) {
+//!     let max = values.iter().fold(0.0f32, |max, value| max.max(value.abs()));
+//!     if max > 0.0 {
+//!         scale(simd, values, 1.0 / max);
+//!     }
+//! }
+//!
+//! let mut values = [1.0, -4.0, 2.0];
+//! normalise(&mut values);
+//! assert_eq!(values, [0.25, -1.0, 0.5]);
+//! ```
+//!
+//! <h5>Cloning callees into each specialisation</h5>
+//!
+//! Alternatively, helpers marked with [`#[clonable]`](macro@clonable) can be
+//! listed in `callees = [...]`, which gives every specialisation its own copy
+//! of the function body and of each listed helper, compiled with the features
+//! of that specialisation. Calls to a helper by its name inside of the body,
+//! or inside of another listed helper, then call the copy instead, so the
+//! helpers do not need to be inlined or take a token. The copies are defined
+//! where the specialised function is, so helpers in other modules are listed
+//! by their path (such as `util::helper`) and imported with `use`, and should
+//! refer to any items they use by their full path. Calling a listed helper by
+//! its path would call the original helper instead of the copy, so it is an
+//! error. Helpers marked with `#[clonable]` inside of a specialised module are
+//! not specialised themselves.
+//!
+//! ```
+//! #[maybe_special::clonable]
+//! fn max_abs(values: &[f32]) -> f32 {
+//!     values.iter().fold(0.0, |max, value| max.max(value.abs()))
+//! }
+//!
+//! #[maybe_special::clonable]
+//! fn scale(values: &mut [f32], by: f32) {
+//!     for value in values {
+//!         *value *= by;
+//!     }
+//! }
+//!
+//! #[maybe_special::make_special(
+//!     x86 = ["avx2"],
+//!     aarch64 = ["neon"],
+//!     callees = [max_abs, scale],
+//! )]
+//! pub fn normalise(values: &mut [f32]) {
+//!     let max = max_abs(values);
+//!     if max > 0.0 {
+//!         scale(values, 1.0 / max);
+//!     }
+//! }
+//!
+//! let mut values = [1.0, -4.0, 2.0];
+//! normalise(&mut values);
+//! assert_eq!(values, [0.25, -1.0, 0.5]);
+//! ```
+//!
+//! # Testing every specialisation
+//! Passing `test = [...]` alongside the specialisations generates a `#[test]`
+//! named `<name>_matches_generic`, which calls every specialisation supported
+//! by the current CPU with each of the given inputs, and asserts that they
+//! return the same result as the generic impl. Each input is a tuple of
+//! arguments. Alternatively, `test = random(count)` generates `count` random
+//! inputs instead, which requires every parameter to implement [`Random`],
+//! [`Clone`] and [`Debug`](core::fmt::Debug).
+//!
+//! This is especially useful for manual implementations, as it checks the
+//! requirement that they always return the same result as the generic impl.
+//! Tests cannot be generated for methods, associated functions, or `async`
+//! functions.
+//!
+//! ```
+//! # fn dot_product_avx2(a: [u32; 16], b: [u32; 16]) -> u32 {
+//! #     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! # }
+//! #[maybe_special::make_special(
+//!     x86 = ["avx2"] => unsafe dot_product_avx2,
+//!     x86 = ["sse4.1"],
+//!     test = [([1; 16], [2; 16]), ([3; 16], [0; 16])],
+//! )]
+//! pub fn dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//!
+//! #[maybe_special::make_special(x86 = ["avx2", "fma"], test = random(256))]
+//! pub fn scale(a: Vec<f32>, by: f32) -> Vec<f32> {
+//!     a.iter().map(|a| a * by).collect()
+//! }
+//! ```
+//!
+//! # Benchmarking every specialisation
+//! Similarly, passing `bench = [...]` or `bench = random(count)` generates a
+//! function named `<name>_bench` next to the specialised function, which times
+//! the generic impl and every specialisation supported by the current CPU on
+//! the same inputs. It takes a number of iterations over the inputs, and
+//! returns a [`Timing`] for each specialisation which was run. This can be
+//! called from a benchmark or an example to check whether each specialisation
+//! is actually worth having.
+//!
+//! The inputs are cloned before each iteration, so every parameter must
+//! implement [`Clone`], but the cloning is not included in the timings.
+//! Benchmarks require the `std` feature, and cannot be generated for methods,
+//! associated functions, generic functions, or `async` functions.
+//!
+//! ```
+//! #[maybe_special::make_special(
+//!     x86 = ["avx512f", "avx512vl"],
+//!     x86 = ["avx2"],
+//!     bench = [([1; 16], [2; 16]), ([3; 16], [0; 16])],
+//! )]
+//! pub fn dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//!
+//! for timing in dot_product_bench(1000) {
+//!     println!("{timing}");
+//! }
+//! ```
+//!
+//! # Inspecting the selected specialisation
+//! Passing `accessors = true` alongside the specialisations generates an
+//! accessor named `<name>_selected` next to the function, which returns the
+//! [`Variant`] selected for the current CPU. This is the same specialisation
+//! the function dispatches to, so it can be used to log which variant is in
+//! use, or to group performance data by variant. When static dispatch skips the
+//! selection, the generic impl is reported, as that is what is called (compiled
+//! with the features enabled at compile-time). For methods, the accessor is an
+//! associated function of the same type.
+//!
+//! The accessors are placed next to the function, so an associated function
+//! which does not take `self` or mention `Self` must be specialised through its
+//! `impl` block (see [use on methods](#use-on-methods)) to use them.
+//!
+//! ```
+//! #[maybe_special::make_special(
+//!     x86 = ["avx512f", "avx512vl"],
+//!     x86 = ["avx2"],
+//!     accessors = true,
+//! )]
+//! pub fn dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//!
+//! // Prints e.g. "dot_product: x86+avx512f,avx512vl"
+//! println!("dot_product: {}", dot_product_selected());
+//! ```
+//!
+//! # Forcing a specialisation
+//! The `accessors` option also generates an accessor named `<name>_variants`,
+//! which returns every [`Variant`] of the function for the current
+//! architecture. With the `override` feature enabled, any of these can be
+//! passed to `maybe_special::set_override` to force specialised functions
+//! called on the current thread to use it, which allows testing every
+//! specialisation against the generic impl on the same machine. Variants that
+//! are not supported by the current CPU are refused.
+//!
+//! Enabling the `override` feature disables caching of the selected
+//! specialisation, so it should only be enabled for tests (for example through
+//! `[dev-dependencies]`).
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"], accessors = true)]
+//! pub fn sum(a: &[u32]) -> u32 {
+//!     a.iter().sum()
+//! }
+//!
+//! # #[cfg(feature = "override")]
+//! # {
+//! for variant in sum_variants() {
+//!     if maybe_special::set_override(variant).is_ok() {
+//!         assert_eq!(sum(&[1, 2, 3]), 6);
+//!     }
+//! }
+//!
+//! maybe_special::clear_override();
+//! # }
+//! ```
+//!
+//! # Hoisting dispatch out of loops
+//! The `accessors` option also generates an accessor named `<name>_resolve`,
+//! which performs the selection once and returns the selected specialisation,
+//! so that it can be called repeatedly in a hot loop without dispatching
+//! again. For functions using function pointer dispatch (see [dispatch
+//! types](#dispatch-types)) this is the function pointer itself, otherwise it
+//! is a copyable closure which calls the selected specialisation. It shares
+//! the cached selection of the function, so it returns the same
+//! specialisation the function dispatches to. The selection is made when the
+//! accessor is called, so overrides set afterwards do not apply to the
+//! returned function.
+//!
+//! The accessor is not generated for `async` functions, for `unsafe` functions
+//! using jump table dispatch, or for functions taking `impl Trait` parameters
+//! or returning `impl Trait`.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"], accessors = true)]
+//! pub fn dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//!
+//! let dot_product = dot_product_resolve();
+//! let total: u32 = (0..1024).map(|_| dot_product([1; 16], [2; 16])).sum();
+//! assert_eq!(total, 32768);
+//! ```
+//!
+//! # Disabling features at run-time
+//! With the `env` feature enabled, features can be disabled without rebuilding
+//! through environment variables, which are read the first time each feature
+//! is detected:
+//!
+//! - `MAYBE_SPECIAL_DISABLE` takes a comma separated list of features that
+//!   will never be used, e.g. `MAYBE_SPECIAL_DISABLE=avx512f,avx2`. Features
+//!   implying one of them are not used either, so disabling `avx2` also stops a
+//!   specialisation on `avx512f` from being selected.
+//! - `MAYBE_SPECIAL_FORCE` takes the name of a [`Variant`], e.g. `generic` or
+//!   `x86+avx2,fma`, and only allows the features of that variant to be used.
+//!   When it is set, `MAYBE_SPECIAL_DISABLE` is ignored.
+//!
+//! Empty values are treated as if the variable was not set. These can only
+//! disable features, a feature that is not supported by the current CPU is
+//! never used. Static dispatch is also not affected, as it is resolved at
+//! compile-time.
+//!
+//! # Architectures without run-time detection
+//! WebAssembly has no run-time feature detection, so specialisations for the
+//! `wasm` architecture (which covers both `wasm32` and `wasm64`) only support
+//! static dispatch, and must be marked with `static`. Each one is selected when
+//! its features (such as `simd128` or `relaxed-simd`) are enabled at
+//! compile-time, e.g. with `-C target-feature=+simd128`, and the generic impl is
+//! used otherwise. A specialisation with a manual implementation calls it
+//! directly when its features are enabled, as there is no dynamic dispatch to
+//! reach it from. The same applies to the `sparc64`, `csky`, `hexagon`, `m68k`
+//! and `bpf` architectures.
+//!
+//! ```
+//! fn dot_product_simd128(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//!
+//! #[maybe_special::make_special(
+//!     static wasm = ["relaxed-simd"],
+//!     static wasm = ["simd128"] => unsafe dot_product_simd128,
+//!     x86 = ["avx2"],
+//! )]
+//! pub fn fast_dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//! ```
+//!
 //! # `no_std` support
 //! By default, this macro utilises [`std::arch`], however this can be disabled
 //! by disabling the `std` feature. When the `std` feature is disabled, the code
 //! generated will instead use the unstable [`std_detect`] module, which must be
 //! included manually.
 //!
+//! On x86 and x86_64, enabling the `cpuid` feature along with disabling the
+//! `std` feature makes the generated code detect features with the `cpuid` and
+//! `xgetbv` instructions directly instead, which works on stable Rust. This
+//! also checks that the OS has enabled the registers used by AVX, AVX-512, AMX
+//! and APX, like [`std::arch`] does. It is available as
+//! `maybe_special::cpuid::detect` as well, so it can be used as a custom
+//! detection backend (see below).
+//!
+//! ```toml
+//! [dependencies]
+//! maybe_special = { version = "1.1", default-features = false, features = ["cpuid"] }
+//! ```
+//!
+//! Similarly, on AArch64 and RISC-V Linux, enabling the `auxv` feature along
+//! with disabling the `std` feature makes the generated code read the
+//! `AT_HWCAP`/`AT_HWCAP2` entries of the auxiliary vector with `getauxval`,
+//! along with the `riscv_hwprobe` syscall on RISC-V. This only requires libc
+//! to be linked (e.g. static musl binaries or embedded Linux), rather than
+//! [`std_detect`]. It is available as `maybe_special::auxv::detect` as well,
+//! and reports every feature as unsupported on other operating systems.
+//!
+//! A few features are not reported by the kernel through either of these, so
+//! they cannot be used in a specialisation when this backend is selected: `tme`
+//! and `pauth-lr` on AArch64, and `rv32e`, `rv128i`, `zam`, `q`, `h`, `s`,
+//! `zfinx`, `zdinx`, `zhinx`, `zhinxmin`, `zkr`, `zk`, `svnapot`, `svpbmt`,
+//! `svinval` and `sstc` on RISC-V.
+//!
+//! ```toml
+//! [dependencies]
+//! maybe_special = { version = "1.1", default-features = false, features = ["auxv"] }
+//! ```
+//!
+//! Alternatively, a custom detection backend can be given with `detect =
+//! path::to::detect`, which is called as a `fn(&str) -> bool` with the name of
+//! each feature, or `detect = path::to::detect!`, which is invoked as a macro in
+//! the same way as `is_*_feature_detected!`. This replaces [`std::arch`] and
+//! [`std_detect`] entirely, so it can be used to read features from somewhere
+//! else (e.g. HWCAP or CPUID) on stable `no_std` targets. The results are still
+//! stored in the shared feature cache, so the same backend should be used for
+//! every specialised function in a binary.
+//!
+//! ```
+//! mod hwcap {
+//!     pub fn has_feature(feature: &str) -> bool {
+//!         // Your detection logic here, e.g. reading a table provided by the
+//!         // bootloader.
+//! #         let _ = feature;
+//!         false
+//!     }
+//! }
+//!
+//! #[maybe_special::make_special(
+//!     aarch64 = ["sve2"],
+//!     aarch64 = ["dotprod"],
+//!     detect = hwcap::has_feature,
+//! )]
+//! pub fn fast_dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//! ```
+//!
 //! # Dispatch types
 //! When calling the outer function, this macro utilises a dispatch function to
 //! figure out which specialisation to use. The different dispatch methods are
@@ -144,58 +673,158 @@
 //!
 //! <h5>Function pointer dispatch</h5>
 //!
-//! This is the default dispatch method. This macro generates a static mutable
+//! This is the default dispatch method. This macro generates a static atomic
 //! function pointer that is called upon calling the outer function. Upon first
 //! call, instead of directly calling a specialisation or the generic impl, it
 //! instead calls an initialiser function that checks for all enabled features
-//! at run-time, and determines the best specialisation to call. This result is
-//! saved so that all future calls are fast.
+//! at run-time using the shared feature cache, and determines the best
+//! specialisation to call. This result is saved so that all future calls are
+//! fast.
 //!
 //! <h5>Jump table dispatch</h5>
 //!
-//! When applied to a function that contains generics, `impl` types, or is
-//! `async`, function pointer dispatch will not work. This is because all types
-//! must be specified exactly to generate a function pointer. `async` functions
-//! under the hood desugar to returning an `impl Future<Output = Ty>`,
+//! When applied to a function that contains generics, `impl` types, is `async`,
+//! or is a method, function pointer dispatch will not work. This is because all
+//! types must be specified exactly to generate a function pointer. `async`
+//! functions under the hood desugar to returning an `impl Future<Output = Ty>`,
 //! therefore making them also behave as if they were generic. Therefore, this
 //! macro falls back to a jump table dispatch method, where instead of utilising
 //! a function pointer directly, it instead utilises an index into a jump table.
 //! This dispatch method is almost identical to the function pointer method,
 //! however can be a few cycles slower.
 //!
+//! <h5>Async functions</h5>
+//!
+//! The body of an `async` function runs whenever its future is polled rather
+//! than when the function is called, so enabling features on the function
+//! alone would only cover creating the future. Instead, the specialisation is
+//! selected once when the future is created, and the returned future polls
+//! the future of that specialisation from a function with its features
+//! enabled. Every specialisation has its own type of future, so its body is
+//! compiled with those features once it is inlined into that function.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! pub async fn checksum(data: Vec<u8>) -> u32 {
+//!     data.iter().map(|byte| *byte as u32).sum()
+//! }
+//! ```
+//!
+//! <h5>Returning iterators and closures</h5>
+//!
+//! Functions returning `impl Iterator` or `impl Fn` are lazy in the same way
+//! as `async` functions, as most of their work happens when the iterator is
+//! advanced or the closure is called. These are handled like futures: the
+//! returned iterator calls `next`, `next_back` and `fold` on the iterator of
+//! the selected specialisation from a function with its features enabled, and
+//! the returned closure calls the closure of the selected specialisation in
+//! the same way. `impl DoubleEndedIterator`, `impl ExactSizeIterator`,
+//! `impl FnMut`, `impl FnOnce` and `impl Future` are supported too.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! pub fn squares(values: &[f32]) -> impl Iterator<Item = f32> + '_ {
+//!     values.iter().map(|value| value * value)
+//! }
+//!
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! pub fn scaler(factor: f32) -> impl Fn(&[f32]) -> f32 {
+//!     move |values| values.iter().map(|value| value * factor).sum()
+//! }
+//!
+//! assert_eq!(squares(&[1.0, 2.0, 3.0]).sum::<f32>(), 14.0);
+//! assert_eq!(scaler(2.0)(&[1.0, 2.0, 3.0]), 12.0);
+//! ```
+//!
+//! Any other `impl Trait` return type, such as `impl Display` or
+//! `Option<impl Iterator>`, cannot be combined like this, as every
+//! specialisation would return its own type of it. Instead, each specialisation
+//! calls the generic impl from a function with its features enabled, so they
+//! all return the same type, and any work done with it after the function has
+//! returned runs without those features. These functions cannot take a feature
+//! token or use manual impls, and `async` functions returning `impl Trait` are
+//! an error.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], aarch64 = ["neon"])]
+//! pub fn total(values: &[u32]) -> impl core::fmt::Display {
+//!     values.iter().sum::<u32>()
+//! }
+//!
+//! assert_eq!(total(&[1, 2, 3]).to_string(), "6");
+//! ```
+//!
+//! <h5>Eager initialisation</h5>
+//!
+//! By default, the specialisation is selected on the first call, so that call
+//! pays the cost of detecting every feature. With `init = eager`, this is
+//! done when the program starts instead, using a constructor in the
+//! `.init_array` section, so the function pointer (or jump table index) is
+//! already selected by the first call. This is only supported on ELF targets
+//! such as Linux, and every other target falls back to selecting the
+//! specialisation on the first call.
+//!
+//! The constructor of a method cannot refer to `Self`, so for methods (and
+//! any other function specialised inside of an `impl` block) only the
+//! feature detection is run when the program starts. The first call still
+//! selects the jump table index, but no longer has to detect any features.
+//! Methods inside of a specialised module share the selection of the
+//! module instead, which is made when the program starts.
+//!
+//! ```
+//! #[maybe_special::make_special(x86 = ["avx2"], init = eager)]
+//! pub fn fast_dot_product(a: [u32; 16], b: [u32; 16]) -> u32 {
+//!     a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+//! }
+//! ```
+//!
+//! [`std::arch`]: https://doc.rust-lang.org/std/arch/index.html
 //! [`std_detect`]: https://doc.rust-lang.org/nightly/std_detect/index.html
 
-extern crate proc_macro;
+#![no_std]
 
-use proc_macro::TokenStream;
-use proc_macro2::{Ident, Span};
-use venial::{Error, Item};
+#[cfg(feature = "std")]
+extern crate std;
 
-mod arch;
-mod builder;
-mod r#macro;
-mod spec;
-
-pub(crate) use arch::Architecture;
-pub(crate) use builder::FnBuilder;
-pub(crate) use spec::Specialisation;
-
-pub(crate) fn generic_ident() -> Ident {
-    Ident::new("_generic", Span::call_site())
-}
+#[cfg(all(
+    feature = "auxv",
+    any(
+        target_arch = "aarch64",
+        target_arch = "arm64ec",
+        target_arch = "riscv32",
+        target_arch = "riscv64"
+    )
+))]
+pub mod auxv;
+#[cfg(feature = "std")]
+mod bench;
+mod cache;
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod cpuid;
+mod dispatch;
+#[cfg(feature = "env")]
+mod env;
+#[cfg(feature = "override")]
+mod overrides;
+mod random;
+mod token;
+mod variant;
 
-/// Refer to the [crate-level documentation](crate)
-#[proc_macro_attribute]
-pub fn make_special(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let orig_func = match venial::parse_item(item.into()) {
-        Ok(Item::Function(func)) => func,
-        Ok(item) => {
-            return Error::new_at_span(item.span(), "make_special can only accept fn items")
-                .to_compile_error()
-                .into();
-        }
-        Err(err) => return err.to_compile_error().into(),
-    };
+#[cfg(feature = "std")]
+pub use bench::Timing;
+pub use maybe_special_macros::{clonable, make_special};
+#[cfg(feature = "override")]
+pub use overrides::{UnsupportedVariant, clear_override, set_override};
+pub use random::{Random, Rng};
+pub use token::{GenericToken, Token};
+pub use variant::Variant;
 
-    r#macro::make_special(attr.into(), orig_func).into()
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "std")]
+    pub use crate::bench::bench;
+    pub use crate::cache::{FEATURES, FeatureCache, MAX_FEATURES, detect};
+    pub use crate::dispatch::{DispatchSlot, cast};
+    #[cfg(feature = "override")]
+    pub use crate::overrides::allowed;
 }
@@ -0,0 +1,54 @@
+use crate::Variant;
+use core::{cell::Cell, fmt};
+
+std::thread_local! {
+    static OVERRIDE: Cell<Option<&'static Variant>> = const { Cell::new(None) };
+}
+
+/// The error returned by [`set_override`] when the features of a variant are
+/// not available on the current CPU.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct UnsupportedVariant(pub Variant);
+
+impl fmt::Display for UnsupportedVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not supported on the current CPU", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedVariant {}
+
+/// Forces specialised functions called on the current thread to only use the
+/// features of `variant`.
+///
+/// Each function then dispatches to the first of its specialisations that only
+/// requires features of `variant`, so passing one of the variants returned by
+/// a function's `variants` accessor forces that function to use it, and
+/// passing [`Variant::GENERIC`] forces every function to use its generic impl.
+/// Static dispatch is not affected, as it is resolved at compile-time.
+///
+/// Returns an error and leaves the current override unchanged if `variant` is
+/// not supported on the current CPU.
+pub fn set_override(variant: &'static Variant) -> Result<(), UnsupportedVariant> {
+    if !variant.is_supported() {
+        return Err(UnsupportedVariant(*variant));
+    }
+
+    OVERRIDE.with(|cell| cell.set(Some(variant)));
+    Ok(())
+}
+
+/// Removes the override set by [`set_override`] on the current thread.
+pub fn clear_override() {
+    OVERRIDE.with(|cell| cell.set(None));
+}
+
+/// Returns whether `feature` may be used by the current thread.
+#[doc(hidden)]
+#[inline]
+pub fn allowed(feature: &str) -> bool {
+    OVERRIDE.with(|cell| match cell.get() {
+        Some(variant) => variant.features().contains(&feature),
+        None => true,
+    })
+}
@@ -0,0 +1,117 @@
+/// A small deterministic pseudo-random number generator, used to generate the
+/// inputs of equivalence tests.
+pub struct Rng(u64);
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        // A seed of zero would only ever produce zero.
+        Self(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    /// Returns the next random number, using xorshift64*.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Types which can be randomly generated as the inputs of equivalence tests,
+/// see the [crate-level documentation](crate#testing-every-specialisation).
+pub trait Random {
+    fn random(rng: &mut Rng) -> Self;
+}
+
+macro_rules! impl_int {
+    ($($ty:ty),*) => {
+        $(
+            impl Random for $ty {
+                fn random(rng: &mut Rng) -> Self {
+                    rng.next_u64() as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl Random for u128 {
+    fn random(rng: &mut Rng) -> Self {
+        ((rng.next_u64() as u128) << 64) | rng.next_u64() as u128
+    }
+}
+
+impl Random for i128 {
+    fn random(rng: &mut Rng) -> Self {
+        u128::random(rng) as i128
+    }
+}
+
+impl Random for bool {
+    fn random(rng: &mut Rng) -> Self {
+        rng.next_u64() & 1 == 1
+    }
+}
+
+// Floats are kept finite and within a small range, as specialisations may
+// round differently at the extremes of the exponent range.
+impl Random for f32 {
+    fn random(rng: &mut Rng) -> Self {
+        ((rng.next_u64() >> 40) as f32 / (1u64 << 24) as f32 - 0.5) * 2048.0
+    }
+}
+
+impl Random for f64 {
+    fn random(rng: &mut Rng) -> Self {
+        ((rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * 2048.0
+    }
+}
+
+impl<T: Random, const N: usize> Random for [T; N] {
+    fn random(rng: &mut Rng) -> Self {
+        core::array::from_fn(|_| T::random(rng))
+    }
+}
+
+impl<T: Random> Random for Option<T> {
+    fn random(rng: &mut Rng) -> Self {
+        bool::random(rng).then(|| T::random(rng))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Random> Random for std::vec::Vec<T> {
+    fn random(rng: &mut Rng) -> Self {
+        let len = rng.next_u64() % 256;
+        (0..len).map(|_| T::random(rng)).collect()
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Random),*> Random for ($($name,)*) {
+            fn random(rng: &mut Rng) -> Self {
+                ($($name::random(rng),)*)
+            }
+        }
+    };
+}
+
+impl Random for () {
+    fn random(_rng: &mut Rng) -> Self {}
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
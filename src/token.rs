@@ -0,0 +1,38 @@
+use crate::Variant;
+
+/// A zero-sized proof that the target features of a specialisation are
+/// supported by the current CPU, which is passed into the body of a function
+/// specialised with `token = name`, see the [crate-level
+/// documentation](crate#propagating-features-to-callees).
+///
+/// Helpers which are generic over a token are instantiated separately for
+/// each specialisation, and can use [`vectorize`](Token::vectorize) to run
+/// code with the features of that specialisation enabled.
+///
+/// # Safety
+/// A value of the implementing type must only exist when every feature of
+/// [`VARIANT`](Token::VARIANT) is supported by the current CPU.
+pub unsafe trait Token: Copy + Send + Sync + 'static {
+    /// The specialisation whose features this token proves are supported.
+    const VARIANT: Variant;
+
+    /// Calls `f` from a function compiled with the features of this token
+    /// enabled. Code is only compiled with these features once it is inlined
+    /// into `f`, so any helpers called by it should be `#[inline]`.
+    fn vectorize<R>(self, f: impl FnOnce() -> R) -> R;
+}
+
+/// The token passed into the generic impl, which proves that no features are
+/// supported.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GenericToken;
+
+// SAFETY: The generic impl does not enable any features.
+unsafe impl Token for GenericToken {
+    const VARIANT: Variant = Variant::GENERIC;
+
+    #[inline(always)]
+    fn vectorize<R>(self, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+}
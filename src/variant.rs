@@ -0,0 +1,100 @@
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// Describes one of the implementations generated for a specialised function,
+/// either the generic impl or one of its target feature specialisations.
+///
+/// This is returned by the `selected` and `variants` accessors generated
+/// alongside each specialised function. Its [`Display`](fmt::Display)
+/// implementation prints the variant in the form `arch+feature1,feature2`, or
+/// `generic` for the generic impl.
+#[derive(Clone, Copy)]
+pub struct Variant {
+    name: &'static str,
+    arch: Option<&'static str>,
+    features: &'static [&'static str],
+    supported: fn() -> bool,
+}
+
+impl Variant {
+    /// The generic impl, which is used when no specialisation is supported.
+    pub const GENERIC: Self = Self {
+        name: "generic",
+        arch: None,
+        features: &[],
+        supported: || true,
+    };
+
+    #[doc(hidden)]
+    pub const fn new(
+        name: &'static str,
+        arch: &'static str,
+        features: &'static [&'static str],
+        supported: fn() -> bool,
+    ) -> Self {
+        Self {
+            name,
+            arch: Some(arch),
+            features,
+            supported,
+        }
+    }
+
+    /// The name of this variant, in the form `arch+feature1,feature2`.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The architecture this variant was specialised for, or [`None`] for the
+    /// generic impl.
+    pub const fn arch(&self) -> Option<&'static str> {
+        self.arch
+    }
+
+    /// The target features enabled for this variant.
+    pub const fn features(&self) -> &'static [&'static str] {
+        self.features
+    }
+
+    pub const fn is_generic(&self) -> bool {
+        self.arch.is_none()
+    }
+
+    /// Returns whether every feature of this variant is available on the
+    /// current CPU.
+    pub fn is_supported(&self) -> bool {
+        (self.supported)()
+    }
+}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Variant {}
+
+impl Hash for Variant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl fmt::Debug for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Variant")
+            .field("name", &self.name)
+            .field("arch", &self.arch)
+            .field("features", &self.features)
+            .finish()
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}